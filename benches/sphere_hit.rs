@@ -0,0 +1,98 @@
+//! Times ray-sphere intersection on the geometry of the random spheres scene.
+//!
+//! Run with `cargo bench --bench sphere_hit`.
+
+use std::{
+    hint::black_box,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use glam::dvec3 as vec3;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use raytracing_in_one_weekend::{
+    bvh::BvhNode, grid::UniformGrid, material::Lambertian, Hittable, HittableList, Interval,
+    Point3, Ray, Sphere,
+};
+
+const RAY_COUNT: usize = 200_000;
+const RUNS: usize = 5;
+
+fn main() {
+    let world = random_spheres();
+    let rays = random_rays();
+
+    let bvh = BvhNode::new(random_spheres());
+    let grid = UniformGrid::new(random_spheres());
+    report("flat list", &rays, &world);
+    report("bvh", &rays, &bvh);
+    report("grid", &rays, &grid);
+}
+
+/// The spheres of the random spheres scene, all with the same material since only the
+/// intersection is timed.
+fn random_spheres() -> HittableList {
+    let mut world = HittableList::new();
+    let mat = Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)));
+    let mut rng = StdRng::seed_from_u64(0);
+
+    world.add(Box::new(Sphere::new(
+        vec3(0.0, -1000.0, -1.0),
+        1000.0,
+        mat.clone(),
+    )));
+    for a in -11..11 {
+        for b in -11..11 {
+            let center = Point3::new(
+                a as f64 + 0.9 * rng.gen::<f64>(),
+                0.2,
+                b as f64 + 0.9 * rng.gen::<f64>(),
+            );
+            world.add(Box::new(Sphere::new(center, 0.2, mat.clone())));
+        }
+    }
+    for center in [
+        vec3(0.0, 1.0, 0.0),
+        vec3(-4.0, 1.0, 0.0),
+        vec3(4.0, 1.0, 0.0),
+    ] {
+        world.add(Box::new(Sphere::new(center, 1.0, mat.clone())));
+    }
+
+    world
+}
+
+/// Rays from the scene's camera position towards random points around its focus.
+fn random_rays() -> Vec<Ray> {
+    let mut rng = StdRng::seed_from_u64(1);
+    let lookfrom = vec3(13.0, 2.0, 3.0);
+
+    (0..RAY_COUNT)
+        .map(|_| {
+            let target = vec3(
+                rng.gen_range(-12.0..12.0),
+                rng.gen_range(-2.0..4.0),
+                rng.gen_range(-12.0..12.0),
+            );
+            Ray::new(lookfrom, target - lookfrom)
+        })
+        .collect()
+}
+
+fn report(name: &str, rays: &[Ray], world: &impl Hittable) {
+    let mut best = Duration::MAX;
+    let mut hits = 0;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        hits = rays
+            .iter()
+            .filter(|r| {
+                black_box(world.hit(black_box(r), Interval::new(0.001, f64::INFINITY))).is_some()
+            })
+            .count();
+        best = best.min(start.elapsed());
+    }
+
+    let ns_per_ray = best.as_nanos() as f64 / rays.len() as f64;
+    println!("{name:>10}: {ns_per_ray:8.1} ns/ray ({hits} hits)");
+}
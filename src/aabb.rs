@@ -0,0 +1,142 @@
+use crate::{Interval, Point3, Ray, Vec3};
+
+/// An axis-aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub x: Interval,
+    pub y: Interval,
+    pub z: Interval,
+}
+
+impl Aabb {
+    /// The box containing nothing, which is the identity for `union`.
+    pub const EMPTY: Self = Self {
+        x: Interval::EMPTY,
+        y: Interval::EMPTY,
+        z: Interval::EMPTY,
+    };
+
+    /// The box containing all of space, for unbounded objects.
+    pub const UNIVERSE: Self = Self {
+        x: Interval::UNIVERSE,
+        y: Interval::UNIVERSE,
+        z: Interval::UNIVERSE,
+    };
+
+    pub fn new(x: Interval, y: Interval, z: Interval) -> Self {
+        Self { x, y, z }
+    }
+
+    /// Treats the two points `a` and `b` as extrema for the bounding box, so they don't need to
+    /// be in any particular order.
+    pub fn from_points(a: Point3, b: Point3) -> Self {
+        let min = a.min(b);
+        let max = a.max(b);
+
+        Self {
+            x: Interval::new(min.x, max.x),
+            y: Interval::new(min.y, max.y),
+            z: Interval::new(min.z, max.z),
+        }
+    }
+
+    /// Returns the smallest box enclosing both boxes.
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self {
+            x: Interval::new_enclosing(self.x, other.x),
+            y: Interval::new_enclosing(self.y, other.y),
+            z: Interval::new_enclosing(self.z, other.z),
+        }
+    }
+
+    /// Returns a copy of the box where no axis is thinner than a small epsilon, so that flat
+    /// shapes like quads still get a box rays can hit.
+    pub fn pad(&self) -> Self {
+        const DELTA: f64 = 0.0001;
+        let pad = |ax: Interval| {
+            if ax.size() < DELTA {
+                ax.expand(DELTA)
+            } else {
+                ax
+            }
+        };
+
+        Self {
+            x: pad(self.x),
+            y: pad(self.y),
+            z: pad(self.z),
+        }
+    }
+
+    /// Returns whether the box contains anything and is finite on every axis.
+    pub fn is_valid(&self) -> bool {
+        self.x.is_valid() && self.y.is_valid() && self.z.is_valid()
+    }
+
+    pub fn center(&self) -> Point3 {
+        0.5 * (self.min() + self.max())
+    }
+
+    /// Returns the vector from the minimum corner of the box to the maximum corner.
+    pub fn diagonal(&self) -> Vec3 {
+        self.max() - self.min()
+    }
+
+    /// Returns the index of the axis along which the box is longest, 0 to 2 for x to z.
+    pub fn longest_axis(&self) -> usize {
+        let size = self.diagonal();
+        if size.x > size.y && size.x > size.z {
+            0
+        } else if size.y > size.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn min(&self) -> Point3 {
+        Point3::new(self.x.min, self.y.min, self.z.min)
+    }
+
+    pub fn max(&self) -> Point3 {
+        Point3::new(self.x.max, self.y.max, self.z.max)
+    }
+
+    pub fn axis_interval(&self, n: usize) -> Interval {
+        match n {
+            1 => self.y,
+            2 => self.z,
+            _ => self.x,
+        }
+    }
+
+    /// Returns whether the ray passes through the box within `ray_t`, using the slab method.
+    pub fn hit(&self, r: &Ray, mut ray_t: Interval) -> bool {
+        for axis in 0..3 {
+            let ax = self.axis_interval(axis);
+
+            // A ray parallel to the slab never enters or leaves it, so it only has to start
+            // inside. Dividing by zero here would produce NaNs for origins on the boundary.
+            if r.direction()[axis] == 0.0 {
+                if r.origin[axis] < ax.min || r.origin[axis] > ax.max {
+                    return false;
+                }
+                continue;
+            }
+
+            let adinv = 1.0 / r.direction()[axis];
+
+            let t0 = (ax.min - r.origin[axis]) * adinv;
+            let t1 = (ax.max - r.origin[axis]) * adinv;
+
+            ray_t.min = ray_t.min.max(t0.min(t1));
+            ray_t.max = ray_t.max.min(t0.max(t1));
+
+            if ray_t.max <= ray_t.min {
+                return false;
+            }
+        }
+
+        true
+    }
+}
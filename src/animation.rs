@@ -0,0 +1,247 @@
+use std::{fs, path::Path};
+
+use glam::DVec2;
+use image::ImageResult;
+
+use crate::{
+    bvh::BvhNode,
+    camera::{Camera, CameraParams, MotionVector},
+    color::Color,
+    stats::RenderStats,
+    Hittable, HittableList, Vec3,
+};
+
+/// Renders `frame_count` frames of an animation to `frame_0000.png`, `frame_0001.png`, ... in
+/// `dir`, which is created if needed, and returns the statistics of every frame.
+///
+/// `scene` builds the world and camera of each frame from its index. The camera seed of frame
+/// `n` is offset by `n`, so every frame gets its own noise but rendering the same frame again
+/// gives the same image.
+pub fn render_animation(
+    frame_count: usize,
+    dir: impl AsRef<Path>,
+    scene: impl FnMut(usize) -> (HittableList, CameraParams),
+) -> ImageResult<Vec<RenderStats>> {
+    render_frames(frame_count, dir.as_ref(), scene, |cam, world| {
+        cam.render_image(world)
+    })
+}
+
+/// Renders an animation like `render_animation`, but blends every frame with the ones before it
+/// through a `TemporalAccumulator`, which hides much of the noise of low sample counts.
+pub fn render_animation_temporal(
+    frame_count: usize,
+    dir: impl AsRef<Path>,
+    blend: TemporalBlend,
+    scene: impl FnMut(usize) -> (HittableList, CameraParams),
+) -> ImageResult<Vec<RenderStats>> {
+    let mut accumulator = TemporalAccumulator::new(blend);
+    render_frames(frame_count, dir.as_ref(), scene, |cam, world| {
+        accumulator.render_frame(cam, world)
+    })
+}
+
+fn render_frames(
+    frame_count: usize,
+    dir: &Path,
+    mut scene: impl FnMut(usize) -> (HittableList, CameraParams),
+    mut render: impl FnMut(&mut Camera, &BvhNode) -> (Vec<Color>, RenderStats),
+) -> ImageResult<Vec<RenderStats>> {
+    fs::create_dir_all(dir)?;
+
+    (0..frame_count)
+        .map(|frame| {
+            let (world, mut params) = scene(frame);
+            params.seed = params.seed.wrapping_add(frame as u64);
+
+            let mut cam = Camera::new(params);
+            let (image, stats) = render(&mut cam, &BvhNode::new(world));
+            cam.save_image(&image, dir.join(format!("frame_{frame:04}.png")))?;
+
+            Ok(stats)
+        })
+        .collect()
+}
+
+/// How a `TemporalAccumulator` blends frames.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TemporalBlend {
+    /// Weight of the new render in each pixel, from 0 for keeping the history to 1 for not
+    /// blending at all. Lower values hide more noise but take longer to catch up with changes
+    /// in lighting.
+    pub alpha: f64,
+    /// How much farther or closer, relative to its distance, the surface a pixel's history
+    /// shows may be than where the surface it now shows was
+    pub depth_tolerance: f64,
+    /// Smallest cosine of the angle between the normals of the history and the surface it is
+    /// blended into
+    pub min_normal_cos: f64,
+}
+
+impl Default for TemporalBlend {
+    fn default() -> Self {
+        Self {
+            alpha: 0.2,
+            depth_tolerance: 0.05,
+            min_normal_cos: 0.9,
+        }
+    }
+}
+
+/// Blends each frame of an animation with the frames before it, following the surfaces as they
+/// move across the image.
+///
+/// Every pixel looks up where its surface was in the previous frame with
+/// `Camera::render_motion_vectors` and blends in the color there. Surfaces that were hidden
+/// before, like the ground behind an object that moved away, would pick up the color of what
+/// covered them, so history is rejected where the previous frame saw something at a different
+/// depth or facing another way. Those pixels, and those showing the background, start over
+/// from the new render.
+pub struct TemporalAccumulator {
+    blend: TemporalBlend,
+    history: Option<History>,
+}
+
+/// The blended previous frame and the first-hit AOVs its history is checked against.
+struct History {
+    camera: Camera,
+    color: Vec<Color>,
+    depth: Vec<f64>,
+    normals: Vec<Vec3>,
+}
+
+impl TemporalAccumulator {
+    pub fn new(blend: TemporalBlend) -> Self {
+        Self {
+            blend,
+            history: None,
+        }
+    }
+
+    /// Renders the next frame through `cam` and returns it blended with the frames before, along
+    /// with the statistics of the render.
+    pub fn render_frame(
+        &mut self,
+        cam: &mut Camera,
+        world: &impl Hittable,
+    ) -> (Vec<Color>, RenderStats) {
+        let (mut color, stats) = cam.render_image(world);
+        let depth = cam.render_depth(world);
+        let normals = cam.render_normals(world);
+
+        if let Some(history) = &self.history {
+            let motion = cam.render_motion_vectors(world, &history.camera);
+            let (x0, y0, x1, _) = cam.region();
+            let width = (x1 - x0) as usize;
+            for (idx, pixel_color) in color.iter_mut().enumerate() {
+                let (i, j) = (x0 + (idx % width) as i32, y0 + (idx / width) as i32);
+                let previous = motion[idx]
+                    .and_then(|mv| history.reproject(i, j, mv, normals[idx], &self.blend));
+                if let Some(previous) = previous {
+                    *pixel_color = previous.lerp(*pixel_color, self.blend.alpha);
+                }
+            }
+        }
+
+        self.history = Some(History {
+            camera: cam.clone(),
+            color: color.clone(),
+            depth,
+            normals,
+        });
+
+        (color, stats)
+    }
+}
+
+impl History {
+    /// Returns the color of pixel i, j in the previous frame, found with its motion vector,
+    /// unless it showed another surface than the one with `normal` the pixel shows now.
+    fn reproject(
+        &self,
+        i: i32,
+        j: i32,
+        mv: MotionVector,
+        normal: Vec3,
+        blend: &TemporalBlend,
+    ) -> Option<Color> {
+        let previous = DVec2::new(i as f64, j as f64) - mv.offset;
+        let (x0, y0, x1, y1) = self.camera.region();
+        let (pi, pj) = (previous.x.round() as i32, previous.y.round() as i32);
+        if !(x0..x1).contains(&pi) || !(y0..y1).contains(&pj) {
+            return None;
+        }
+
+        let idx = ((pj - y0) * (x1 - x0) + (pi - x0)) as usize;
+        let same_depth = (self.depth[idx] - mv.previous_depth).abs()
+            <= blend.depth_tolerance * mv.previous_depth;
+        let same_facing = self.normals[idx].dot(normal) >= blend.min_normal_cos;
+
+        (same_depth && same_facing).then_some(self.color[idx])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{material::Lambertian, Point3, Sphere};
+
+    fn camera(seed: u64) -> Camera {
+        Camera::builder()
+            .image_width(32)
+            .aspect_ratio(1.0)
+            .samples_per_pixel(2)
+            .max_depth(4)
+            .lookfrom(Point3::new(0.0, 0.0, 10.0))
+            .lookat(Point3::ZERO)
+            .defocus_angle(0.0)
+            .seed(seed)
+            .progress(false)
+            .build()
+    }
+
+    /// A wall, with a ball in front of it unless `without_ball`.
+    fn world(without_ball: bool) -> HittableList {
+        let mat = Arc::new(Lambertian::new(Color::splat(0.5)));
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Point3::new(0.0, 0.0, -1000.0),
+            995.0,
+            mat.clone(),
+        )));
+        if !without_ball {
+            world.add(Box::new(Sphere::new(Point3::ZERO, 0.8, mat)));
+        }
+
+        world
+    }
+
+    #[test]
+    fn still_frames_blend_everywhere() {
+        let world = world(false);
+        let mut accumulator = TemporalAccumulator::new(TemporalBlend::default());
+        let (first, _) = accumulator.render_frame(&mut camera(0), &world);
+        let (blended, _) = accumulator.render_frame(&mut camera(1), &world);
+
+        let (second, _) = camera(1).render_image(&world);
+        for ((first, second), blended) in first.iter().zip(&second).zip(&blended) {
+            assert!(blended.abs_diff_eq(first.lerp(*second, 0.2), 1e-12));
+        }
+    }
+
+    #[test]
+    fn disoccluded_pixels_drop_their_history() {
+        let mut accumulator = TemporalAccumulator::new(TemporalBlend::default());
+        accumulator.render_frame(&mut camera(0), &world(false));
+        let (blended, _) = accumulator.render_frame(&mut camera(1), &world(true));
+
+        let (current, _) = camera(1).render_image(&world(true));
+        let (previous, _) = camera(0).render_image(&world(false));
+        // The middle pixels showed the ball and now show the wall, the corners the wall twice.
+        let middle = 16 * 32 + 16;
+        assert_eq!(blended[middle], current[middle]);
+        assert!(blended[0].abs_diff_eq(previous[0].lerp(current[0], 0.2), 1e-12));
+    }
+}
@@ -0,0 +1,279 @@
+use std::cmp::Ordering;
+
+use rayon::prelude::*;
+
+use crate::{aabb::Aabb, HitRecord, Hittable, HittableList, Interval, Ray};
+
+/// Nodes over fewer objects than this are built on the current thread, since splitting them up
+/// between threads costs more than it saves.
+const PARALLEL_BUILD_THRESHOLD: usize = 4096;
+
+/// A bounding volume hierarchy node. Rays that miss a node's bounding box skip everything
+/// inside it.
+///
+/// Large lists, like the triangles of a big mesh, are sorted and split up on all cores with rayon.
+pub struct BvhNode {
+    left: Child,
+    /// Leaf nodes holding a single object have no right child.
+    right: Option<Child>,
+    bbox: Aabb,
+}
+
+/// A child of a `BvhNode`: another node of the tree, or one of the objects it was built from.
+enum Child {
+    Node(Box<BvhNode>),
+    Object(Box<dyn Hittable>),
+}
+
+impl Child {
+    fn as_hittable(&self) -> &dyn Hittable {
+        match self {
+            Child::Node(node) => node.as_ref(),
+            Child::Object(object) => object.as_ref(),
+        }
+    }
+}
+
+impl BvhNode {
+    pub fn new(list: HittableList) -> Self {
+        Self::from_objects(list.into_iter().collect(), PARALLEL_BUILD_THRESHOLD)
+    }
+
+    /// Returns the bounding box of every node in the tree along with its depth, 0 for this node,
+    /// in depth-first order. Shown in a scene, e.g. as wireframes colored by depth, they reveal
+    /// badly split nodes, like all of them bloated by a huge ground sphere.
+    pub fn to_boxes(&self) -> Vec<(Aabb, usize)> {
+        let mut boxes = vec![];
+        self.collect_boxes(0, &mut boxes);
+
+        boxes
+    }
+
+    fn collect_boxes(&self, depth: usize, boxes: &mut Vec<(Aabb, usize)>) {
+        boxes.push((self.bbox, depth));
+        for child in [Some(&self.left), self.right.as_ref()]
+            .into_iter()
+            .flatten()
+        {
+            if let Child::Node(node) = child {
+                node.collect_boxes(depth + 1, boxes);
+            }
+        }
+    }
+
+    /// Tests `r` against the children that its box lets through, with `hit`, and returns the
+    /// closest hit.
+    fn hit_children(
+        &self,
+        r: &Ray,
+        ray_t: Interval,
+        mut hit: impl FnMut(&dyn Hittable, Interval) -> Option<HitRecord>,
+    ) -> Option<HitRecord> {
+        if !self.bbox.hit(r, ray_t) {
+            return None;
+        }
+
+        let hit_left = hit(self.left.as_hittable(), ray_t);
+        let Some(right) = &self.right else {
+            return hit_left;
+        };
+
+        let closest_so_far = hit_left.as_ref().map_or(ray_t.max, |rec| rec.t);
+        let hit_right = hit(
+            right.as_hittable(),
+            Interval::new(ray_t.min, closest_so_far),
+        );
+
+        hit_right.or(hit_left)
+    }
+
+    /// Builds the node over `objects`, splitting up those over `parallel_threshold` objects or
+    /// more between threads.
+    fn from_objects(mut objects: Vec<Box<dyn Hittable>>, parallel_threshold: usize) -> Self {
+        // Splitting along the longest axis of the node keeps the children apart, and builds the
+        // same tree for the same objects every time.
+        let bbox = objects.iter().fold(Aabb::EMPTY, |bbox, object| {
+            bbox.union(&object.bounding_box())
+        });
+        let axis = bbox.longest_axis();
+
+        let (left, right) = match objects.len() {
+            0 => (Child::Object(Box::new(HittableList::new())), None),
+            1 => (Child::Object(objects.remove(0)), None),
+            2 => {
+                let right = Child::Object(objects.remove(1));
+                (Child::Object(objects.remove(0)), Some(right))
+            }
+            n if n >= parallel_threshold => {
+                objects.par_sort_by(|a, b| box_compare(a.as_ref(), b.as_ref(), axis));
+
+                let right = objects.split_off(objects.len() / 2);
+                let (left, right) = rayon::join(
+                    || Self::from_objects(objects, parallel_threshold),
+                    || Self::from_objects(right, parallel_threshold),
+                );
+                (
+                    Child::Node(Box::new(left)),
+                    Some(Child::Node(Box::new(right))),
+                )
+            }
+            _ => {
+                objects.sort_by(|a, b| box_compare(a.as_ref(), b.as_ref(), axis));
+
+                let right = objects.split_off(objects.len() / 2);
+                let left = Self::from_objects(objects, parallel_threshold);
+                let right = Self::from_objects(right, parallel_threshold);
+                (
+                    Child::Node(Box::new(left)),
+                    Some(Child::Node(Box::new(right))),
+                )
+            }
+        };
+
+        Self { left, right, bbox }
+    }
+}
+
+fn box_compare(a: &dyn Hittable, b: &dyn Hittable, axis: usize) -> Ordering {
+    let a_axis_interval = a.bounding_box().axis_interval(axis);
+    let b_axis_interval = b.bounding_box().axis_interval(axis);
+
+    a_axis_interval.min.total_cmp(&b_axis_interval.min)
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        self.hit_children(r, ray_t, |child, ray_t| child.hit(r, ray_t))
+    }
+
+    fn hit_counting(&self, r: &Ray, ray_t: Interval, nodes_visited: &mut u64) -> Option<HitRecord> {
+        *nodes_visited += 1;
+        self.hit_children(r, ray_t, |child, ray_t| {
+            child.hit_counting(r, ray_t, nodes_visited)
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn bvh_node_count(&self) -> u64 {
+        1 + self.left.as_hittable().bvh_node_count()
+            + self
+                .right
+                .as_ref()
+                .map_or(0, |right| right.as_hittable().bvh_node_count())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::Arc;
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        material::Lambertian, random_vec3_range, random_vec3_unit, shapes::Quad, shapes::Triangle,
+        Sphere, Vec3,
+    };
+
+    /// A seeded jumble of spheres, quads and triangles over a large ground sphere.
+    pub(crate) fn scene(count: usize) -> HittableList {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mat = Arc::new(Lambertian::new(Vec3::splat(0.5)));
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            995.0,
+            mat.clone(),
+        )));
+        for n in 0..count {
+            let p = random_vec3_range(&mut rng, -5.0..5.0);
+            let object: Box<dyn Hittable> = match n % 3 {
+                0 => Box::new(Sphere::new(p, rng.gen_range(0.05..0.5), mat.clone())),
+                1 => Box::new(Quad::new(
+                    p,
+                    random_vec3_range(&mut rng, -0.5..0.5),
+                    random_vec3_range(&mut rng, -0.5..0.5),
+                    mat.clone(),
+                )),
+                _ => Box::new(Triangle {
+                    a: p,
+                    b: p + random_vec3_range(&mut rng, -0.5..0.5),
+                    c: p + random_vec3_range(&mut rng, -0.5..0.5),
+                    mat: mat.clone(),
+                }),
+            };
+            world.add(object);
+        }
+
+        world
+    }
+
+    /// Asserts that `a` and `b` hit the same surfaces along a seeded set of rays through the
+    /// scene.
+    pub(crate) fn assert_same_hits(a: &dyn Hittable, b: &dyn Hittable) {
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut hits = 0;
+        for _ in 0..5000 {
+            let r = Ray::new(
+                random_vec3_range(&mut rng, -8.0..8.0),
+                random_vec3_unit(&mut rng),
+            );
+            let ray_t = Interval::new(0.001, f64::INFINITY);
+            match (a.hit(&r, ray_t), b.hit(&r, ray_t)) {
+                (None, None) => {}
+                (Some(rec_a), Some(rec_b)) => {
+                    hits += 1;
+                    assert!((rec_a.t - rec_b.t).abs() < 1e-9, "t differs along {r:?}");
+                    assert!(rec_a.p.abs_diff_eq(rec_b.p, 1e-9), "p differs along {r:?}");
+                    assert!(
+                        rec_a.normal.abs_diff_eq(rec_b.normal, 1e-9),
+                        "normal differs along {r:?}"
+                    );
+                }
+                (rec_a, rec_b) => panic!(
+                    "only one hits along {r:?}: {:?} vs {:?}",
+                    rec_a.map(|rec| rec.t),
+                    rec_b.map(|rec| rec.t)
+                ),
+            }
+        }
+        assert!(hits > 100, "too few rays hit anything to compare");
+    }
+
+    #[test]
+    fn bvh_hits_like_list() {
+        let bvh = BvhNode::new(scene(300));
+        assert_same_hits(&bvh, &scene(300));
+    }
+
+    #[test]
+    fn parallel_build_hits_like_serial_build() {
+        let objects = |world: HittableList| world.into_iter().collect();
+        let parallel = BvhNode::from_objects(objects(scene(300)), 16);
+        let serial = BvhNode::from_objects(objects(scene(300)), usize::MAX);
+        assert_same_hits(&parallel, &serial);
+    }
+
+    #[test]
+    fn to_boxes_lists_every_node_within_its_parent() {
+        let bvh = BvhNode::new(scene(300));
+        let boxes = bvh.to_boxes();
+        assert_eq!(boxes.len() as u64, bvh.bvh_node_count());
+        assert_eq!(boxes[0], (bvh.bounding_box(), 0));
+        // Depth-first order: the nearest earlier box one level up is the parent.
+        for (n, &(bbox, depth)) in boxes.iter().enumerate().skip(1) {
+            let &(parent, _) = boxes[..n]
+                .iter()
+                .rev()
+                .find(|&&(_, d)| d + 1 == depth)
+                .expect("node without a parent");
+            for axis in 0..3 {
+                assert!(parent.axis_interval(axis).min <= bbox.axis_interval(axis).min);
+                assert!(parent.axis_interval(axis).max >= bbox.axis_interval(axis).max);
+            }
+        }
+    }
+}
@@ -1,19 +1,50 @@
+use std::{
+    f64::consts::PI,
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use glam::{DMat3, DVec2};
+use image::{GrayImage, ImageBuffer, ImageFormat, ImageResult, Luma, Rgb, RgbImage};
+use indicatif::{ProgressBar, ProgressStyle};
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+use rayon::prelude::*;
+
 use crate::{
-    color::{self, Color},
-    random_vec3_on_unit_disc, random_vec3_unit, vec3, Hittable, Interval, Point3, Ray, Vec3,
+    aabb::Aabb,
+    color::{self, Color, GammaMode, ToneMap},
+    environment::{EnvironmentMap, ProceduralSky},
+    material::Scattered,
+    pdf::{random_cosine_direction, Onb, Pdf},
+    random_vec3_on_unit_disc,
+    sampling::{self, BlueNoiseMask},
+    stats::RenderStats,
+    vec3, HitRecord, Hittable, HittableList, Interval, Point3, Ray, RayKind, Vec3,
 };
 
+#[derive(Clone)]
 pub struct Camera {
     image_width: i32,
     samples_per_pixel: i32,
     pixel_samples_scale: f64,
     max_depth: i32,
     image_height: i32,
+    /// Pixels `x0..x1` by `y0..y1` of the image that get rendered and output
+    region: (i32, i32, i32, i32),
 
     /// Variation angle of rays through each pixel
     defocus_angle: f64,
 
+    projection: Projection,
+    shutter: Shutter,
     center: Point3,
+    /// Direction the camera is looking in
+    forward: Vec3,
     pixel00_loc: Point3,
     pixel_delta_u: Vec3,
     pixel_delta_v: Vec3,
@@ -21,9 +52,83 @@ pub struct Camera {
     // Defocus disk radius
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
+
+    pixel_sampling: PixelSampling,
+    /// Number of strata along each side of a pixel with `PixelSampling::Stratified`
+    sqrt_spp: i32,
+    recip_sqrt_spp: f64,
+    /// Per-pixel rotation of the sample sequence
+    blue_noise: BlueNoiseMask,
+
+    refinement: RefinementStrategy,
+    background: Background,
+    parallel: bool,
+    exposure: f64,
+    tone_map: ToneMap,
+    gamma: GammaMode,
+    progress: bool,
+    report_stats: bool,
+    tile_size: i32,
+    tile_order: TileOrder,
+    lights: Option<Arc<dyn Hittable>>,
+    heatmap: bool,
+    debug: DebugMode,
+    shadow_bias: f64,
+    clamp_luminance: Option<f64>,
+    linear_output: bool,
+    spectral: bool,
+    light_paths_per_pixel: i32,
+    /// Whether the render in progress adds the caustics pass, so camera paths leave its light out
+    splats_caustics: bool,
+    seed: u64,
+
+    counters: Counters,
+    /// Stops the render in progress once set, see `render_cancellable`
+    cancel: Option<Arc<AtomicBool>>,
 }
 
+/// Weight of a sample drawn with density `pdf`, out of two strategies that could have drawn it,
+/// the other with density `other_pdf`. Veach's power heuristic with an exponent of 2.
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b <= 0.0 {
+        return 0.0;
+    }
+
+    a / (a + b)
+}
+
+/// Maps `t` from 0 to 1 onto a heatmap going from blue through green to red.
+fn heat(t: f64) -> Color {
+    let t = t.clamp(0.0, 1.0);
+    if t < 0.5 {
+        Color::new(0.0, 0.0, 1.0).lerp(Color::new(0.0, 1.0, 0.0), 2.0 * t)
+    } else {
+        Color::new(0.0, 1.0, 0.0).lerp(Color::new(1.0, 0.0, 0.0), 2.0 * t - 1.0)
+    }
+}
+
+/// Wavelengths in nanometers that spectral renders trace the red, green and blue channels at.
+const SPECTRAL_BANDS: [(f64, Color); 3] = [
+    (610.0, Color::new(1.0, 0.0, 0.0)),
+    (550.0, Color::new(0.0, 1.0, 0.0)),
+    (465.0, Color::new(0.0, 0.0, 1.0)),
+];
+
+/// Number of bounces every path gets before Russian roulette may end it.
+const ROULETTE_START_DEPTH: i32 = 3;
+
+/// Small enough to balance the load between threads, and for the pixels of a tile to hit mostly
+/// the same parts of the BVH.
+pub const DEFAULT_TILE_SIZE: i32 = 32;
+
+/// Keeps rays from hitting the surface they bounce off, in scenes about a few units across.
+pub const DEFAULT_SHADOW_BIAS: f64 = 0.001;
+
+#[derive(Clone)]
 pub struct CameraParams {
+    /// Ratio of the image width over its height. The height is rounded down to whole pixels, so
+    /// the image's actual aspect ratio can be slightly wider.
     pub aspect_ratio: f64,
     pub image_width: i32,
     pub samples_per_pixel: i32,
@@ -34,33 +139,533 @@ pub struct CameraParams {
     pub vup: Vec3,
     pub defocus_angle: f64,
     pub focus_dist: f64,
+    /// Puts the focus plane through this point instead of at `focus_dist`, so it stays sharp
+    /// wherever the camera moves, e.g. `Some(lookat)`.
+    pub focus_on: Option<Point3>,
+    pub refinement: RefinementStrategy,
+    pub background: Background,
+    /// Render scanlines on all cores with rayon instead of on the calling thread
+    pub parallel: bool,
+    /// Renders with the same seed, scene and parameters produce identical images
+    pub seed: u64,
+    pub projection: Projection,
+    /// Multiplier for the linear colors written to images, 1.0 leaves them as rendered. Applied
+    /// before tone mapping, so highlights it pushes past 1.0 still roll off.
+    pub exposure: f64,
+    pub tone_map: ToneMap,
+    pub gamma: GammaMode,
+    /// Whether to show a progress bar on stderr. When off, rendering writes nothing to stderr
+    /// except for the `report_stats` report.
+    pub progress: bool,
+    /// Whether to print the `RenderStats` of every finished render to stderr, e.g. to compare
+    /// the speed of acceleration structures
+    pub report_stats: bool,
+    /// Side length in pixels of the square tiles the image is rendered in, see
+    /// `DEFAULT_TILE_SIZE`. `RefinementStrategy::Progressive` samples the whole image and
+    /// ignores it.
+    pub tile_size: i32,
+    /// The order tiles are rendered in.
+    pub tile_order: TileOrder,
+    /// Emissive objects that diffuse bounces cast shadow rays towards, which cuts the noise of
+    /// small lights a lot. They must also be part of the world to be seen and to cast light.
+    pub lights: Option<Arc<dyn Hittable>>,
+    pub pixel_sampling: PixelSampling,
+    pub shutter: Shutter,
+    /// Renders and outputs only the pixels `x0..x1` by `y0..y1` of the image, given as
+    /// `(x0, y0, x1, y1)`. The pixels are the same as in a full render. Parts outside the image
+    /// are cut off.
+    pub region: Option<(i32, i32, i32, i32)>,
+    /// Outputs how many samples each pixel took instead of the image, from blue for none to red
+    /// for `samples_per_pixel`. Mostly useful with `RefinementStrategy::Adaptive`.
+    pub heatmap: bool,
+    pub debug: DebugMode,
+    /// Distance along every scattered and shadow ray that hits are ignored within, so rounding
+    /// errors don't make rays hit the surface they start from. Too small for the scale of the
+    /// scene and surfaces shadow themselves with speckles (shadow acne), too large and shadows
+    /// come loose from the objects casting them. See `DEFAULT_SHADOW_BIAS`.
+    pub shadow_bias: f64,
+    /// Scales samples brighter than this luminance down to it, which removes most fireflies, the
+    /// lone bright pixels of rarely found light paths, at the cost of darkening highlights a bit
+    pub clamp_luminance: Option<f64>,
+    /// Writes linear colors without a transfer function, ignoring `gamma`, for compositing.
+    /// Image files are written as 16-bit PNGs to keep the precision of the darks, so their path
+    /// needs a `.png` extension.
+    pub linear_output: bool,
+    /// Splits paths that reach dispersive glass, see `Dielectric::with_dispersion`, into one
+    /// per color channel, refracted at the wavelength of that channel, which spreads white light
+    /// into rainbows. Those paths take three times as long to trace. When off, dispersive glass
+    /// refracts every color alike.
+    pub spectral: bool,
+    /// Paths traced from `lights` per pixel for caustics, 0 for none. They follow light through
+    /// glass and mirrors to the diffuse surfaces the camera sees, and add it to the pixels those
+    /// are seen in. Camera paths reach such light only by chance, so caustics cast through glass
+    /// by small lights come out as speckles without this.
+    ///
+    /// Camera paths then leave that light to the light paths, instead of counting it twice.
+    /// Only renders of the finished image, like `Camera::render` and `render_to_buffer`, trace
+    /// the light paths. `render_pass`, `render_scanlines` and `render_progressive` find the
+    /// caustics with camera paths alone, as if this was 0.
+    /// Caustics of emissive objects that aren't in `lights`, or that can't be traced from with
+    /// `Hittable::sample_surface`, are lost on the surfaces the camera sees directly. The light
+    /// paths connect to the center of the lens, so caustics don't blur with `defocus_angle`.
+    pub light_paths_per_pixel: i32,
+}
+
+/// Replaces path tracing with a view of the geometry the camera rays hit first. The colors are
+/// written like those of a normal render.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum DebugMode {
+    /// Path tracing as usual.
+    #[default]
+    Off,
+    /// The outward surface normal, mapped from [-1,1] to [0,1] on each axis.
+    Normals,
+    /// White at the camera, fading to black at `max_distance` and beyond.
+    Depth { max_distance: f64 },
+    /// How many BVH nodes the ray was tested against, from blue for none to red for `max_nodes`
+    /// or more.
+    BvhNodes { max_nodes: u64 },
+    /// Ambient occlusion: how open the surface is, from white where nothing is near it to black
+    /// in creases and where objects touch, as if lit equally from every direction. Pixels
+    /// showing the background are white.
+    AmbientOcclusion(AoParams),
+}
+
+/// How `DebugMode::AmbientOcclusion` probes the surroundings of the surfaces the camera sees.
+#[derive(Clone, Copy, PartialEq)]
+pub struct AoParams {
+    /// Number of rays sent out per camera sample, cosine-weighted around the normal
+    pub samples: i32,
+    /// Objects farther away than this don't occlude, so the image shows the local shape
+    /// instead of how enclosed the scene is as a whole
+    pub max_distance: f64,
+}
+
+/// Where the surface seen through a pixel was in an earlier frame, see
+/// `Camera::render_motion_vectors`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MotionVector {
+    /// How many pixels the surface moved across the image since the earlier frame
+    pub offset: DVec2,
+    /// Distance from the earlier frame's camera to where the surface was then, measured like
+    /// `Camera::render_depth`
+    pub previous_depth: f64,
+}
+
+impl CameraParams {
+    /// Aims the camera at the center of `bbox` and backs it off along its current view
+    /// direction until the whole box fits in the image, keeping `vfov` and `aspect_ratio`. The
+    /// focus plane goes through the center. Orthographic cameras get a viewport wide enough
+    /// instead.
+    ///
+    /// Everything is framed, so leave out objects like a huge ground sphere that shouldn't be.
+    /// Invalid boxes, e.g. of an empty list or of unbounded objects, leave the parameters as
+    /// they are, see `frame_objects`.
+    pub fn frame_scene(&mut self, bbox: &Aabb) {
+        if !bbox.is_valid() {
+            return;
+        }
+
+        let direction = (self.lookfrom - self.lookat)
+            .try_normalize()
+            .unwrap_or(vec3(0.0, 0.0, 1.0));
+        // Fit the sphere around the box, so it fits from any direction.
+        let radius = (0.5 * bbox.diagonal().length()).max(0.001);
+
+        let distance = match &mut self.projection {
+            Projection::Perspective => {
+                let half_height = (self.vfov / 2.0).to_radians().tan();
+                let half_width = half_height * self.aspect_ratio;
+                let half_angle = half_height.min(half_width).atan();
+                radius / half_angle.sin()
+            }
+            Projection::Orthographic { width } => {
+                *width = 2.0 * radius * self.aspect_ratio.max(1.0);
+                2.0 * radius
+            }
+        };
+
+        self.lookat = bbox.center();
+        self.lookfrom = self.lookat + distance * direction;
+        self.focus_dist = distance;
+        self.focus_on = None;
+    }
+
+    /// Frames the objects of `world` like `frame_scene`, leaving out unbounded ones like an
+    /// `InfinitePlane` used as the ground.
+    pub fn frame_objects(&mut self, world: &HittableList) {
+        self.frame_scene(&world.finite_bounding_box());
+    }
+}
+
+impl Default for CameraParams {
+    /// A 400 pixel wide 16:9 image of 100 samples per pixel and up to 50 bounces, with a 90°
+    /// field of view looking down -z from the origin and no defocus blur.
+    fn default() -> Self {
+        Self {
+            aspect_ratio: 16.0 / 9.0,
+            image_width: 400,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            vfov: 90.0,
+            lookfrom: Point3::ZERO,
+            lookat: Point3::new(0.0, 0.0, -1.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            defocus_angle: 0.0,
+            focus_dist: 10.0,
+            focus_on: None,
+            refinement: RefinementStrategy::Uniform,
+            background: Background::default(),
+            parallel: true,
+            seed: 0,
+            projection: Projection::Perspective,
+            exposure: 1.0,
+            tone_map: ToneMap::Clamp,
+            gamma: GammaMode::default(),
+            progress: true,
+            report_stats: false,
+            tile_size: DEFAULT_TILE_SIZE,
+            tile_order: TileOrder::Scanline,
+            lights: None,
+            pixel_sampling: PixelSampling::LowDiscrepancy,
+            shutter: Shutter::Global,
+            region: None,
+            heatmap: false,
+            debug: DebugMode::Off,
+            shadow_bias: DEFAULT_SHADOW_BIAS,
+            clamp_luminance: None,
+            linear_output: false,
+            spectral: false,
+            light_paths_per_pixel: 0,
+        }
+    }
+}
+
+/// Builds a `Camera` from the `CameraParams` defaults, changing only the parameters that are
+/// set, e.g. `Camera::builder().image_width(800).samples_per_pixel(50).build()`.
+#[derive(Clone, Default)]
+pub struct CameraBuilder {
+    params: CameraParams,
+}
+
+/// Defines a setter for each of the given `CameraParams` fields.
+macro_rules! setters {
+    ($($field:ident: $ty:ty),* $(,)?) => {
+        $(
+            #[doc = concat!("Sets `CameraParams::", stringify!($field), "`.")]
+            pub fn $field(mut self, $field: $ty) -> Self {
+                self.params.$field = $field;
+                self
+            }
+        )*
+    };
+}
+
+impl CameraBuilder {
+    setters! {
+        aspect_ratio: f64,
+        image_width: i32,
+        samples_per_pixel: i32,
+        max_depth: i32,
+        vfov: f64,
+        lookfrom: Point3,
+        lookat: Point3,
+        vup: Vec3,
+        defocus_angle: f64,
+        focus_dist: f64,
+        focus_on: Option<Point3>,
+        refinement: RefinementStrategy,
+        background: Background,
+        parallel: bool,
+        seed: u64,
+        projection: Projection,
+        exposure: f64,
+        tone_map: ToneMap,
+        gamma: GammaMode,
+        progress: bool,
+        report_stats: bool,
+        tile_size: i32,
+        tile_order: TileOrder,
+        lights: Option<Arc<dyn Hittable>>,
+        pixel_sampling: PixelSampling,
+        shutter: Shutter,
+        region: Option<(i32, i32, i32, i32)>,
+        heatmap: bool,
+        debug: DebugMode,
+        shadow_bias: f64,
+        clamp_luminance: Option<f64>,
+        linear_output: bool,
+        spectral: bool,
+        light_paths_per_pixel: i32,
+    }
+
+    /// Returns the parameters set so far, e.g. to adjust them with `CameraParams::frame_scene`.
+    pub fn params(self) -> CameraParams {
+        self.params
+    }
+
+    pub fn build(self) -> Camera {
+        Camera::new(self.params)
+    }
+}
+
+/// When each pixel is exposed during the shutter interval [0,1), which only shows with moving
+/// objects.
+#[derive(Clone, Copy, Default)]
+pub enum Shutter {
+    /// Every pixel is exposed over the whole interval.
+    #[default]
+    Global,
+    /// Each row is exposed for `duration` of the interval, starting later the further down the
+    /// image it is, like a CMOS sensor. Fast-moving objects come out skewed.
+    Rolling { duration: f64 },
+}
+
+/// How sample points are placed within a pixel.
+#[derive(Clone, Copy, Default)]
+pub enum PixelSampling {
+    /// Points of the R2 sequence, rotated per pixel with a blue-noise mask.
+    #[default]
+    LowDiscrepancy,
+    /// One random point in every cell of a square grid over the pixel. `samples_per_pixel`
+    /// is rounded up to the next square number.
+    Stratified,
+}
+
+#[derive(Clone, Copy, Default)]
+pub enum Projection {
+    /// Rays spread out from the camera center, covering `vfov` degrees vertically.
+    #[default]
+    Perspective,
+    /// Rays are parallel to the view direction and start across a viewport `width` world units
+    /// wide, so parallel lines stay parallel in the image. Defocus blur doesn't apply.
+    Orthographic { width: f64 },
+}
+
+/// The color of rays that don't hit anything.
+#[derive(Clone)]
+pub enum Background {
+    /// A single color, e.g. `Color::ZERO` for scenes lit only by emissive materials
+    Solid(Color),
+    /// A vertical gradient blending from the first color straight down to the second color
+    /// straight up
+    Gradient(Color, Color),
+    /// An image of the surroundings, which also lights the scene and whose bright parts are
+    /// sampled directly at diffuse bounces
+    Environment(Arc<EnvironmentMap>),
+    /// A daylight sky whose sun is sampled directly at diffuse bounces, for sharp sun shadows
+    Sky(ProceduralSky),
+}
+
+impl Default for Background {
+    /// The blue-white sky.
+    fn default() -> Self {
+        Self::Gradient(Color::splat(1.0), Color::new(0.5, 0.7, 1.0))
+    }
+}
+
+/// How samples are distributed over the image.
+#[derive(Clone, Copy, Default)]
+pub enum RefinementStrategy {
+    /// Every pixel gets `samples_per_pixel` samples, one sample per pixel per pass.
+    #[default]
+    Uniform,
+    /// Starts with a coarse preview that samples one pixel out of every `initial_stride` in each
+    /// direction and halves the stride every pass. Once every pixel has been sampled, the rest
+    /// of the sample budget goes to the pixels with the highest variance.
+    Progressive { initial_stride: i32 },
+    /// Every pixel gets at least `min_samples` samples, then stops early once the variance of its
+    /// mean luminance drops below `variance_threshold`, or at `samples_per_pixel` at the latest.
+    /// Flat areas finish quickly, while edges and caustics keep sampling.
+    Adaptive {
+        min_samples: i32,
+        variance_threshold: f64,
+    },
+}
+
+/// The order tiles are handed out to the threads of a tiled render. It only changes which parts
+/// of the image finish first, e.g. for a nicer look while watching the render, as every pixel is
+/// rendered the same way wherever its tile comes in the order.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum TileOrder {
+    /// Row by row from the top left, like the scanlines of a uniform render.
+    #[default]
+    Scanline,
+    /// Ring by ring outwards from the tile in the middle, so the center of the image comes first.
+    Spiral,
+    /// Along a Hilbert curve over the image, which keeps consecutive tiles next to each other.
+    Hilbert,
+    /// Shuffled with the camera's seed, which shows the whole image coarsely early on.
+    Random,
+}
+
+impl TileOrder {
+    /// Sorts `tiles`, the top left corners of tiles `tile_size` pixels wide laid out in rows
+    /// from the top left corner of `region`, into this order.
+    fn arrange(
+        self,
+        tiles: &mut [(i32, i32)],
+        region: (i32, i32, i32, i32),
+        tile_size: i32,
+        rng: &mut StdRng,
+    ) {
+        let (x0, y0, x1, y1) = region;
+        let columns = (x1 - x0 + tile_size - 1) / tile_size;
+        let rows = (y1 - y0 + tile_size - 1) / tile_size;
+        let cell = |&(i, j): &(i32, i32)| ((i - x0) / tile_size, (j - y0) / tile_size);
+
+        match self {
+            TileOrder::Scanline => {}
+            TileOrder::Spiral => {
+                let (cx, cy) = ((columns - 1) / 2, (rows - 1) / 2);
+                tiles.sort_by_cached_key(|tile| {
+                    let (dx, dy) = (cell(tile).0 - cx, cell(tile).1 - cy);
+                    // Go around each ring clockwise, starting from its top left corner.
+                    let ring = dx.abs().max(dy.abs());
+                    let along = if dy == -ring {
+                        dx + ring
+                    } else if dx == ring {
+                        3 * ring + dy
+                    } else if dy == ring {
+                        5 * ring - dx
+                    } else {
+                        7 * ring - dy
+                    };
+                    (ring, along)
+                });
+            }
+            TileOrder::Hilbert => {
+                let side = columns.max(rows).max(1) as u32;
+                let side = side.next_power_of_two();
+                tiles.sort_by_cached_key(|tile| {
+                    let (x, y) = cell(tile);
+                    hilbert_index(side, x as u32, y as u32)
+                });
+            }
+            TileOrder::Random => {
+                for n in (1..tiles.len()).rev() {
+                    tiles.swap(n, rng.gen_range(0..=n));
+                }
+            }
+        }
+    }
+}
+
+/// Returns how far along the Hilbert curve over a `side` by `side` grid cell x, y is. `side` must
+/// be a power of two.
+fn hilbert_index(side: u32, mut x: u32, mut y: u32) -> u64 {
+    let mut index = 0;
+    let mut s = side / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as u32;
+        let ry = (y & s > 0) as u32;
+        index += (s as u64) * (s as u64) * ((3 * rx) ^ ry) as u64;
+        // Rotate the quadrant so the curve inside it starts and ends at the right corners.
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - (x & (s - 1));
+                y = s - 1 - (y & (s - 1));
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+
+    index
 }
 
 impl Camera {
+    /// Returns a builder starting from the `CameraParams` defaults.
+    pub fn builder() -> CameraBuilder {
+        CameraBuilder::default()
+    }
+
     pub fn new(params: CameraParams) -> Self {
         let CameraParams {
             aspect_ratio,
             image_width,
             defocus_angle,
             focus_dist,
+            focus_on,
             lookat,
             lookfrom,
             max_depth,
             vfov,
             samples_per_pixel,
             vup,
-            ..
+            refinement,
+            background,
+            parallel,
+            seed,
+            projection,
+            exposure,
+            tone_map,
+            gamma,
+            progress,
+            report_stats,
+            tile_size,
+            tile_order,
+            lights,
+            pixel_sampling,
+            shutter,
+            region,
+            heatmap,
+            debug,
+            shadow_bias,
+            clamp_luminance,
+            linear_output,
+            spectral,
+            light_paths_per_pixel,
         } = params;
+        let gamma = if linear_output {
+            GammaMode::Linear
+        } else {
+            gamma
+        };
+
+        // A stratified grid needs a square number of samples, so round up to the next one.
+        let sqrt_spp = (samples_per_pixel.max(1) as f64).sqrt().ceil() as i32;
+        let samples_per_pixel = match pixel_sampling {
+            PixelSampling::LowDiscrepancy => samples_per_pixel,
+            PixelSampling::Stratified => sqrt_spp * sqrt_spp,
+        };
+
+        // The height is rounded down to whole pixels, but is at least one pixel.
+        let image_height = ((image_width as f64 / aspect_ratio) as i32).max(1);
 
-        let image_height = (image_width as f64 / aspect_ratio) as i32;
+        let region = match region {
+            Some((x0, y0, x1, y1)) => {
+                let (x0, y0) = (x0.clamp(0, image_width), y0.clamp(0, image_height));
+                (
+                    x0,
+                    y0,
+                    x1.clamp(x0, image_width),
+                    y1.clamp(y0, image_height),
+                )
+            }
+            None => (0, 0, image_width, image_height),
+        };
 
         let center = lookfrom;
 
-        // Determine viewport dimensions.
-        let theta = vfov.to_radians();
-        let h = (theta / 2.0).tan();
-        let viewport_height = 2.0 * h * focus_dist;
-        let viewport_width = viewport_height * ((image_width as f64) / (image_height as f64));
+        // The focus plane faces the camera, so only the distance along the view direction counts.
+        let focus_dist = match focus_on {
+            Some(point) => (point - lookfrom).dot((lookat - lookfrom).normalize()),
+            None => focus_dist,
+        };
+
+        // Determine viewport dimensions. They follow the aspect ratio of the rounded image size
+        // rather than `aspect_ratio`, so the pixels stay square.
+        let pixel_aspect = (image_width as f64) / (image_height as f64);
+        let (viewport_width, viewport_height) = match projection {
+            Projection::Perspective => {
+                let theta = vfov.to_radians();
+                let h = (theta / 2.0).tan();
+                let viewport_height = 2.0 * h * focus_dist;
+                (viewport_height * pixel_aspect, viewport_height)
+            }
+            Projection::Orthographic { width } => (width, width / pixel_aspect),
+        };
 
         // Calculate the u,v,w unit basis vectors for the camera coordinate frame.
         let w = (lookfrom - lookat).normalize();
@@ -75,8 +680,13 @@ impl Camera {
         let pixel_delta_u = viewport_u / image_width as f64;
         let pixel_delta_v = viewport_v / image_height as f64;
 
-        // Calculate the location of the upper left pixel.
-        let viewport_upper_left = center - (focus_dist * w) - viewport_u / 2.0 - viewport_v / 2.0;
+        // Calculate the location of the upper left pixel. An orthographic viewport sits on the
+        // camera itself, so nothing between the camera and the focus plane gets clipped.
+        let viewport_center = match projection {
+            Projection::Perspective => center - (focus_dist * w),
+            Projection::Orthographic { .. } => center,
+        };
+        let viewport_upper_left = viewport_center - viewport_u / 2.0 - viewport_v / 2.0;
 
         let pixel00_loc = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v);
 
@@ -86,90 +696,1866 @@ impl Camera {
         Self {
             image_width,
             image_height,
+            region,
             samples_per_pixel,
             max_depth,
             pixel_samples_scale: 1.0 / samples_per_pixel as f64,
+            pixel_sampling,
+            sqrt_spp,
+            recip_sqrt_spp: 1.0 / sqrt_spp as f64,
+            projection,
+            shutter,
             center,
+            forward: -w,
             pixel00_loc,
             pixel_delta_u,
             pixel_delta_v,
             defocus_angle,
             defocus_disk_u: u * defocus_radius,
             defocus_disk_v: v * defocus_radius,
+            blue_noise: BlueNoiseMask::new(seed),
+            refinement,
+            background,
+            parallel,
+            seed,
+            exposure,
+            tone_map,
+            gamma,
+            progress,
+            report_stats,
+            tile_size,
+            tile_order,
+            lights,
+            heatmap,
+            debug,
+            shadow_bias,
+            clamp_luminance,
+            linear_output,
+            spectral,
+            light_paths_per_pixel,
+            splats_caustics: false,
+            counters: Counters::default(),
+            cancel: None,
+        }
+    }
+
+    /// Renders the image as PPM to stdout and returns statistics about the render.
+    pub fn render(&mut self, world: impl Hittable) -> io::Result<RenderStats> {
+        let (image, stats) = self.render_image(&world);
+
+        let (width, height) = self.output_size();
+        let mut out = BufWriter::new(io::stdout().lock());
+        write!(out, "P3\n{} {}\n255\n", width, height)?;
+        for &pixel_color in &image {
+            color::write_color_to(
+                &mut out,
+                self.exposure * pixel_color,
+                self.tone_map,
+                self.gamma,
+            )?;
+        }
+        out.flush()?;
+
+        Ok(stats)
+    }
+
+    /// Renders the image and returns the linear color of every pixel, averaged over its samples
+    /// but before gamma correction.
+    ///
+    /// The buffer is row-major, starting from the top-left pixel of the render region, and its
+    /// length is the region's width times its height.
+    pub fn render_to_buffer(&mut self, world: impl Hittable) -> Vec<Color> {
+        self.render_image(&world).0
+    }
+
+    /// Renders the image like `render_to_buffer`, and also returns statistics about the render.
+    pub fn render_with_stats(&mut self, world: impl Hittable) -> (Vec<Color>, RenderStats) {
+        self.render_image(&world)
+    }
+
+    /// Renders the image like `render_with_stats`, but stops early once `cancel` is set, e.g. by
+    /// the stop button of a GUI on another thread, and returns what was rendered so far.
+    ///
+    /// `Uniform` renders finish the pass they're in, and the image is the average of the passes
+    /// done. With other strategies, pixels that weren't rendered or sampled yet are left black.
+    pub fn render_cancellable(
+        &mut self,
+        world: impl Hittable,
+        cancel: Arc<AtomicBool>,
+    ) -> (Vec<Color>, RenderStats) {
+        self.cancel = Some(cancel);
+        let result = self.render_image(&world);
+        self.cancel = None;
+
+        result
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+    }
+
+    /// Renders the whole image at `width` by `height` pixels instead of the camera's own
+    /// resolution, and returns its linear colors like `render_to_buffer`, e.g. to make previews
+    /// and final renders from the same camera.
+    ///
+    /// Perspective cameras keep their vertical field of view and orthographic ones the width of
+    /// their view, and the other side follows the requested aspect ratio. Sizes with the camera's
+    /// aspect ratio therefore show the same view. The camera's render region is ignored.
+    pub fn render_at(&self, world: &impl Hittable, width: i32, height: i32) -> Vec<Color> {
+        let (width, height) = (width.max(1), height.max(1));
+
+        // Find the viewport back from the pixel grid, and stretch it to the new aspect ratio.
+        let mut viewport_u = self.image_width as f64 * self.pixel_delta_u;
+        let mut viewport_v = self.image_height as f64 * self.pixel_delta_v;
+        let viewport_center = self.pixel00_loc - 0.5 * (self.pixel_delta_u + self.pixel_delta_v)
+            + 0.5 * (viewport_u + viewport_v);
+
+        let stretch =
+            (width as f64 / height as f64) / (self.image_width as f64 / self.image_height as f64);
+        match self.projection {
+            Projection::Perspective => viewport_u *= stretch,
+            Projection::Orthographic { .. } => viewport_v /= stretch,
+        }
+
+        let pixel_delta_u = viewport_u / width as f64;
+        let pixel_delta_v = viewport_v / height as f64;
+        let viewport_upper_left = viewport_center - 0.5 * (viewport_u + viewport_v);
+
+        let mut camera = Camera {
+            image_width: width,
+            image_height: height,
+            region: (0, 0, width, height),
+            pixel00_loc: viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v),
+            pixel_delta_u,
+            pixel_delta_v,
+            ..self.clone()
+        };
+
+        camera.render_image(world).0
+    }
+
+    /// Renders the image as binary PPM (P6) to stdout and returns statistics about the render.
+    pub fn render_binary(&mut self, world: impl Hittable) -> io::Result<RenderStats> {
+        let (image, stats) = self.render_image(&world);
+
+        let (width, height) = self.output_size();
+        let mut out = BufWriter::new(io::stdout().lock());
+        write!(out, "P6\n{} {}\n255\n", width, height)?;
+        for &pixel_color in &image {
+            out.write_all(&self.to_rgb8(pixel_color))?;
+        }
+        out.flush()?;
+
+        Ok(stats)
+    }
+
+    /// Renders the image to an 8-bit image file, e.g. a PNG, encoded with the camera's `gamma`,
+    /// or to a 16-bit linear PNG with `linear_output`. The format is chosen from the extension of
+    /// `path`.
+    pub fn render_to_file(
+        &mut self,
+        world: impl Hittable,
+        path: impl AsRef<Path>,
+    ) -> ImageResult<RenderStats> {
+        let (image, stats) = self.render_image(&world);
+        self.save_image(&image, path)?;
+
+        Ok(stats)
+    }
+
+    /// Writes a buffer from `render_to_buffer` or `render_with_stats` to an image file the way
+    /// `render_to_file` does. The format is chosen from the extension of `path`.
+    pub fn save_image(&self, image: &[Color], path: impl AsRef<Path>) -> ImageResult<()> {
+        if self.linear_output {
+            return self.save_linear_png(image, path);
+        }
+
+        self.save_rgb8(path, |idx| self.to_rgb8(image[idx]))
+    }
+
+    /// Writes a buffer from `render_to_buffer` or `render_with_stats` as a 16-bit PNG of linear
+    /// colors, exposed and tone mapped but without a transfer function, whatever the camera's
+    /// `gamma` and `linear_output` are. Saving the same buffer with `save_image` as well gives a
+    /// display-ready image and a linear one of the same render.
+    pub fn save_linear_png(&self, image: &[Color], path: impl AsRef<Path>) -> ImageResult<()> {
+        let (width, height) = self.output_size();
+        let img = ImageBuffer::from_fn(width as u32, height as u32, |i, j| {
+            let pixel_color = image[(j * width as u32 + i) as usize];
+            let pixel_color = self
+                .tone_map
+                .apply((self.exposure * pixel_color).max(Color::ZERO));
+            Rgb(pixel_color
+                .to_array()
+                .map(|c| (65535.0 * c.clamp(0.0, 1.0)).round() as u16))
+        });
+        img.save_with_format(path, ImageFormat::Png)
+    }
+
+    /// Returns the distance from the camera to the first surface seen through the center of
+    /// every pixel, or `f64::INFINITY` where nothing is hit.
+    ///
+    /// Only the camera ray through the center of each pixel is traced, without defocus blur, so
+    /// depths at the edges of objects belong to one surface instead of being averaged across
+    /// two. The buffer is laid out like the one from `render_to_buffer`.
+    pub fn render_depth(&self, world: &impl Hittable) -> Vec<f64> {
+        self.render_first_hits(world, |r, hit| match hit {
+            Some(rec) => rec.t * r.length_squared().sqrt(),
+            None => f64::INFINITY,
+        })
+    }
+
+    /// Returns the base color of the first surface seen through the center of every pixel, see
+    /// `Material::base_albedo`, or black where nothing is hit. Like `render_depth`, there is no
+    /// anti-aliasing.
+    pub fn render_albedo(&self, world: &impl Hittable) -> Vec<Color> {
+        self.render_first_hits(world, |_, hit| match hit {
+            Some(rec) => rec.mat.base_albedo(&rec),
+            None => Color::ZERO,
+        })
+    }
+
+    /// Returns the world-space outward normal of the first surface seen through the center of
+    /// every pixel, or zero where nothing is hit. Like `render_depth`, there is no
+    /// anti-aliasing.
+    pub fn render_normals(&self, world: &impl Hittable) -> Vec<Vec3> {
+        self.render_first_hits(world, |_, hit| match hit {
+            Some(rec) if rec.front_face => rec.normal,
+            Some(rec) => -rec.normal,
+            None => Vec3::ZERO,
+        })
+    }
+
+    /// Returns the motion vector of the first surface seen through the center of every pixel
+    /// since `previous`, the camera of the frame before, or `None` where nothing is hit or the
+    /// surface was behind `previous`. Like `render_depth`, there is no anti-aliasing.
+    ///
+    /// The surface is taken to have moved by its `HitRecord::motion` since then, so the shutter
+    /// interval of moving objects should span one frame.
+    pub fn render_motion_vectors(
+        &self,
+        world: &impl Hittable,
+        previous: &Camera,
+    ) -> Vec<Option<MotionVector>> {
+        let (x0, y0, x1, _) = self.region;
+        let width = (x1 - x0) as usize;
+        let targets: Vec<Option<Point3>> =
+            self.render_first_hits(world, |_, hit| hit.map(|rec| rec.p - rec.motion));
+
+        targets
+            .into_iter()
+            .enumerate()
+            .map(|(idx, target)| {
+                let pixel = DVec2::new(
+                    (x0 + (idx % width) as i32) as f64,
+                    (y0 + (idx / width) as i32) as f64,
+                );
+                let (previous_pixel, previous_depth) = previous.project(target?)?;
+                Some(MotionVector {
+                    offset: pixel - previous_pixel,
+                    previous_depth,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns where the camera sees `p` in the image, in pixels with the center of pixel i, j
+    /// at (i, j), along with its distance from the camera measured like `render_depth`. Points
+    /// behind the camera give `None`.
+    pub fn project(&self, p: Point3) -> Option<(DVec2, f64)> {
+        match self.projection {
+            Projection::Perspective => {
+                // p - center = s * (pixel00 - center + x * delta_u + y * delta_v), with s > 0.
+                let offset = p - self.center;
+                let basis = DMat3::from_cols(
+                    self.pixel00_loc - self.center,
+                    self.pixel_delta_u,
+                    self.pixel_delta_v,
+                );
+                let c = basis.inverse() * offset;
+                (c.x > 0.0).then(|| (DVec2::new(c.y, c.z) / c.x, offset.length()))
+            }
+            Projection::Orthographic { .. } => {
+                // p = pixel00 + x * delta_u + y * delta_v + t * forward, with t >= 0.
+                let basis = DMat3::from_cols(self.pixel_delta_u, self.pixel_delta_v, self.forward);
+                let c = basis.inverse() * (p - self.pixel00_loc);
+                (c.z >= 0.0).then(|| (DVec2::new(c.x, c.y), c.z * self.forward.length()))
+            }
+        }
+    }
+
+    /// Returns the `Identified::id` of the first object seen through the center of every pixel,
+    /// or `None` where nothing with an ID is hit. Like `render_depth`, there is no anti-aliasing.
+    pub fn render_ids(&self, world: &impl Hittable) -> Vec<Option<u16>> {
+        self.render_first_hits(world, |_, hit| hit.and_then(|rec| rec.object_id))
+    }
+
+    /// Renders the IDs like `render_ids` to a 16-bit grayscale PNG holding the IDs themselves,
+    /// and 0 where there is none, so the pixels of an object can be selected by its ID.
+    pub fn render_ids_to_file(
+        &self,
+        world: &impl Hittable,
+        path: impl AsRef<Path>,
+    ) -> ImageResult<()> {
+        let ids = self.render_ids(world);
+        let (width, height) = self.output_size();
+        let img = ImageBuffer::from_fn(width as u32, height as u32, |i, j| {
+            Luma([ids[(j * width as u32 + i) as usize].unwrap_or(0)])
+        });
+        img.save_with_format(path, ImageFormat::Png)
+    }
+
+    /// Renders the albedo like `render_albedo` to an 8-bit image file, gamma encoded like the
+    /// color image but without exposure or tone mapping.
+    pub fn render_albedo_to_file(
+        &self,
+        world: &impl Hittable,
+        path: impl AsRef<Path>,
+    ) -> ImageResult<()> {
+        let albedo = self.render_albedo(world);
+        self.save_rgb8(path, |idx| {
+            albedo[idx]
+                .to_array()
+                .map(|c| (255.999 * self.gamma.encode(c).clamp(0.0, 1.0)) as u8)
+        })
+    }
+
+    /// Renders the normals like `render_normals` to an 8-bit image file, with each component
+    /// mapped from [-1,1] to [0,1].
+    pub fn render_normals_to_file(
+        &self,
+        world: &impl Hittable,
+        path: impl AsRef<Path>,
+    ) -> ImageResult<()> {
+        let normals = self.render_normals(world);
+        self.save_rgb8(path, |idx| {
+            normals[idx]
+                .to_array()
+                .map(|c| (255.999 * (0.5 * (c + 1.0)).clamp(0.0, 1.0)) as u8)
+        })
+    }
+
+    /// Traces the camera ray through the center of every pixel of the render region, and
+    /// returns `value` of each ray and its first hit in the layout of `render_to_buffer`.
+    fn render_first_hits<T: Send>(
+        &self,
+        world: &impl Hittable,
+        value: impl Fn(&Ray, Option<HitRecord>) -> T + Sync,
+    ) -> Vec<T> {
+        let (x0, y0, x1, y1) = self.region;
+        let row = |j: i32| -> Vec<T> {
+            (x0..x1)
+                .map(|i| {
+                    let r = self.center_ray(i, j);
+                    value(
+                        &r,
+                        world.hit(&r, Interval::new(self.shadow_bias, f64::INFINITY)),
+                    )
+                })
+                .collect()
+        };
+
+        let rows: Vec<Vec<T>> = if self.parallel {
+            (y0..y1).into_par_iter().map(row).collect()
+        } else {
+            (y0..y1).map(row).collect()
+        };
+
+        rows.into_iter().flatten().collect()
+    }
+
+    /// Saves the render region as an 8-bit RGB image, given the bytes of each pixel by its
+    /// index in the buffer.
+    fn save_rgb8(
+        &self,
+        path: impl AsRef<Path>,
+        pixel: impl Fn(usize) -> [u8; 3],
+    ) -> ImageResult<()> {
+        let (width, height) = self.output_size();
+        let img = RgbImage::from_fn(width as u32, height as u32, |i, j| {
+            Rgb(pixel((j * width as u32 + i) as usize))
+        });
+        img.save(path)
+    }
+
+    /// Renders the depth of every pixel like `render_depth` to an 8-bit grayscale image file.
+    /// The nearest surface is white and the farthest black, as are pixels that don't hit
+    /// anything.
+    pub fn render_depth_to_file(
+        &self,
+        world: &impl Hittable,
+        path: impl AsRef<Path>,
+    ) -> ImageResult<()> {
+        let depth = self.render_depth(world);
+        let hits = depth.iter().copied().filter(|d| d.is_finite());
+        let range = hits.fold(Interval::EMPTY, |range, d| {
+            Interval::new(range.min.min(d), range.max.max(d))
+        });
+
+        let (width, height) = self.output_size();
+        let img = GrayImage::from_fn(width as u32, height as u32, |i, j| {
+            let d = depth[(j * width as u32 + i) as usize];
+            if !d.is_finite() {
+                return Luma([0]);
+            }
+            let t = if range.size() > 0.0 {
+                (d - range.min) / range.size()
+            } else {
+                0.0
+            };
+            Luma([(255.999 * (1.0 - t)) as u8])
+        });
+        img.save(path)
+    }
+
+    /// Returns the pixels `x0..x1` by `y0..y1` of the image that are rendered, which is the whole
+    /// image unless `CameraParams::region` is set. Buffers start from its top-left pixel.
+    pub fn region(&self) -> (i32, i32, i32, i32) {
+        self.region
+    }
+
+    /// Width and height of the rendered region, which is the whole image unless a region is set.
+    fn output_size(&self) -> (i32, i32) {
+        let (x0, y0, x1, y1) = self.region;
+        (x1 - x0, y1 - y0)
+    }
+
+    /// Exposes, tone maps and gamma corrects a linear color for 8-bit output.
+    fn to_rgb8(&self, pixel_color: Color) -> [u8; 3] {
+        color::to_rgb8(self.exposure * pixel_color, self.tone_map, self.gamma)
+    }
+
+    /// Renders the image with the camera's `RefinementStrategy` and returns the row-major pixel
+    /// buffer along with statistics about the render.
+    pub(crate) fn render_image(&mut self, world: &impl Hittable) -> (Vec<Color>, RenderStats) {
+        self.counters = Counters::default();
+        self.splats_caustics = self.traces_caustics();
+        let start = Instant::now();
+
+        let image = if let RefinementStrategy::Uniform = self.refinement {
+            let (width, height) = self.output_size();
+            let mut buffer = vec![Color::ZERO; (width * height) as usize];
+
+            let progress = self.progress_bar("Passes", self.samples_per_pixel as u64);
+            let mut passes = 0;
+            while passes < self.samples_per_pixel && !self.cancelled() {
+                self.render_pass(world, &mut buffer, passes as usize);
+                passes += 1;
+                progress.inc(1);
+            }
+            progress.finish_and_clear();
+
+            let scale = 1.0 / passes.max(1) as f64;
+            buffer
+                .iter()
+                .map(|&sum| self.output_value(scale * sum, passes))
+                .collect()
+        } else {
+            self.render_progressive(world, |_| {})
+        };
+        let image = match &self.lights {
+            Some(lights) if self.splats_caustics && !self.cancelled() => {
+                let caustics = self.render_caustics(world, lights.as_ref());
+                image
+                    .iter()
+                    .zip(caustics)
+                    .map(|(&c, caustic)| c + caustic)
+                    .collect()
+            }
+            _ => image,
+        };
+        self.splats_caustics = false;
+
+        let wall_time_secs = start.elapsed().as_secs_f64();
+        let rays_traced = self.counters.rays.load(Ordering::Relaxed);
+        let primary_rays = self.counters.primary_rays.load(Ordering::Relaxed);
+        let total_samples = self.counters.samples.load(Ordering::Relaxed);
+
+        let (image_width, image_height) = self.output_size();
+        let stats = RenderStats {
+            image_width,
+            image_height,
+            samples_per_pixel: self.samples_per_pixel,
+            total_samples,
+            wall_time_secs,
+            rays_traced,
+            primary_rays,
+            secondary_rays: rays_traced - primary_rays,
+            rays_per_sec: rays_traced as f64 / wall_time_secs,
+            mean_bounces: self.counters.bounces.load(Ordering::Relaxed) as f64
+                / total_samples.max(1) as f64,
+            peak_bounce_depth: self.counters.peak_depth.load(Ordering::Relaxed),
+            truncated_paths: self.counters.truncated_paths.load(Ordering::Relaxed),
+            bvh_nodes: world.bvh_node_count(),
+        };
+        if self.report_stats {
+            eprint!("{stats}");
+        }
+
+        (image, stats)
+    }
+
+    /// Traces one sample per pixel over the whole render region, the `pass_index`-th sample of
+    /// each pixel, and adds it to the pixel's sum in `buffer`.
+    ///
+    /// `buffer` is row-major like the rendered image and starts out zeroed. Dividing it by the
+    /// number of passes so far gives a preview that refines with every pass, and after passes
+    /// `0..samples_per_pixel` it's the same image a `Uniform` render produces.
+    ///
+    /// The region is traced in tiles like `render_tiles` does, in the camera's `tile_order`.
+    pub fn render_pass(&self, world: &impl Hittable, buffer: &mut [Color], pass_index: usize) {
+        let s = pass_index as i32;
+        let pass = self.render_in_tiles(&ProgressBar::hidden(), |i, j, tally| {
+            let mut rng = self.sample_rng(i, j, s);
+            self.sample(i, j, s, world, &mut rng, tally)
+        });
+
+        for (pixel, sample) in buffer.iter_mut().zip(pass) {
+            *pixel += sample;
+        }
+    }
+
+    /// Renders the image one scanline at a time, calling `on_scanline` with the row index and the
+    /// averaged linear colors of each row as soon as it's finished.
+    ///
+    /// Rows are delivered exactly once, in order from the top of the image (row 0) to the bottom.
+    /// With a render region, rows are numbered from the top of the region and only hold the
+    /// region's pixels.
+    /// When rendering in parallel, rows are rendered in batches of a few per thread, and a row
+    /// is delivered once every row of its batch is finished.
+    pub fn render_scanlines(
+        &self,
+        world: &impl Hittable,
+        mut on_scanline: impl FnMut(usize, &[Color]),
+    ) {
+        let (_, y0, _, y1) = self.region;
+        let progress = self.progress_bar("Scanlines", (y1 - y0) as u64);
+        let batch_size = if self.parallel {
+            rayon::current_num_threads() as i32 * 4
+        } else {
+            1
+        };
+
+        for batch_start in (y0..y1).step_by(batch_size as usize) {
+            let batch = batch_start..(batch_start + batch_size).min(y1);
+            let rows: Vec<_> = if self.parallel {
+                batch
+                    .into_par_iter()
+                    .map(|j| self.render_scanline(j, world, &progress))
+                    .collect()
+            } else {
+                batch
+                    .map(|j| self.render_scanline(j, world, &progress))
+                    .collect()
+            };
+
+            for (j, row) in (batch_start..).zip(&rows) {
+                on_scanline((j - y0) as usize, row);
+            }
         }
+
+        progress.finish_and_clear();
+    }
+
+    fn render_scanline(&self, j: i32, world: &impl Hittable, progress: &ProgressBar) -> Vec<Color> {
+        let mut tally = Tally::default();
+        let (x0, _, x1, _) = self.region;
+        let row = (x0..x1)
+            .map(|i| self.render_pixel(i, j, world, &mut tally))
+            .collect();
+        self.counters.add(&tally);
+        progress.inc(1);
+
+        row
     }
 
-    pub fn render(&mut self, world: impl Hittable) {
-        println!("P3");
-        println!("{} {}", self.image_width, self.image_height);
-        println!("255");
+    /// Renders the image in square tiles of `tile_size` pixels, which are spread over all cores
+    /// when rendering in parallel, and returns the row-major pixel buffer.
+    ///
+    /// Tiles are handed out in the camera's `tile_order`. Every pixel is rendered the same way
+    /// no matter which tile it's in or when, so the image is identical to one rendered with
+    /// `render_scanlines` or with any other tile size or order.
+    fn render_tiles(&self, world: &impl Hittable) -> Vec<Color> {
+        let progress = self.progress_bar("Tiles", self.tiles().len() as u64);
+        let image = self.render_in_tiles(&progress, |i, j, tally| {
+            if self.cancelled() {
+                return Color::ZERO;
+            }
+            self.render_pixel(i, j, world, tally)
+        });
+        progress.finish_and_clear();
+
+        image
+    }
+
+    /// The top left corners of the tiles of `tile_size` pixels covering the render region, in
+    /// the camera's `tile_order`.
+    fn tiles(&self) -> Vec<(i32, i32)> {
+        let tile_size = self.tile_size.max(1);
+        let (x0, y0, x1, y1) = self.region;
+        let mut tiles: Vec<(i32, i32)> = (y0..y1)
+            .step_by(tile_size as usize)
+            .flat_map(|j| (x0..x1).step_by(tile_size as usize).map(move |i| (i, j)))
+            .collect();
+        self.tile_order
+            .arrange(&mut tiles, self.region, tile_size, &mut self.rng(u64::MAX));
+
+        tiles
+    }
+
+    /// Computes `pixel` for every pixel of the render region tile by tile, see `render_tiles`,
+    /// ticking `progress` after each tile, and returns the values in row-major order.
+    fn render_in_tiles(
+        &self,
+        progress: &ProgressBar,
+        pixel: impl Fn(i32, i32, &mut Tally) -> Color + Sync,
+    ) -> Vec<Color> {
+        let tile_size = self.tile_size.max(1);
+        let (x0, y0, x1, y1) = self.region;
+        let tiles = self.tiles();
+
+        let render_tile = |&tile: &(i32, i32)| {
+            let (i0, j0) = tile;
+            let (i1, j1) = ((i0 + tile_size).min(x1), (j0 + tile_size).min(y1));
+
+            let mut tally = Tally::default();
+            let pixels: Vec<Color> = (j0..j1)
+                .flat_map(|j| (i0..i1).map(move |i| (i, j)))
+                .map(|(i, j)| pixel(i, j, &mut tally))
+                .collect();
+            self.counters.add(&tally);
+            progress.inc(1);
 
-        for j in 0..self.image_height {
-            eprintln!("Scanlines remaining: {}", self.image_height - j);
-            for i in 0..self.image_width {
-                let mut pixel_color = Color::default();
-                for _ in 0..self.samples_per_pixel {
-                    let r = self.get_ray(i, j);
-                    pixel_color += Self::ray_color(&r, self.max_depth, &world);
+            (tile, pixels)
+        };
+        // Bridging hands the tiles to the threads one by one in order, where splitting `tiles`
+        // up front would give each thread a run of them.
+        let rendered: Vec<((i32, i32), Vec<Color>)> = if self.parallel {
+            tiles.iter().par_bridge().map(render_tile).collect()
+        } else {
+            tiles.iter().map(render_tile).collect()
+        };
+
+        // Copy every tile's rows into place in the image.
+        let (width, height) = self.output_size();
+        let width = width as usize;
+        let mut image = vec![Color::ZERO; width * height as usize];
+        for &((i0, j0), ref pixels) in &rendered {
+            let tile_width = ((i0 + tile_size).min(x1) - i0) as usize;
+            for (row, tile_row) in pixels.chunks(tile_width).enumerate() {
+                let start = ((j0 - y0) as usize + row) * width + (i0 - x0) as usize;
+                image[start..start + tile_width].copy_from_slice(tile_row);
+            }
+        }
+
+        image
+    }
+
+    fn render_pixel(&self, i: i32, j: i32, world: &impl Hittable, tally: &mut Tally) -> Color {
+        let RefinementStrategy::Adaptive {
+            min_samples,
+            variance_threshold,
+        } = self.refinement
+        else {
+            let mut pixel_color = Color::default();
+            for s in 0..self.samples_per_pixel {
+                let mut rng = self.sample_rng(i, j, s);
+                pixel_color += self.sample(i, j, s, world, &mut rng, tally);
+            }
+
+            return self.output_value(
+                self.pixel_samples_scale * pixel_color,
+                self.samples_per_pixel,
+            );
+        };
+
+        let mut pixel = PixelStats::default();
+        while pixel.count < self.samples_per_pixel {
+            let mut rng = self.sample_rng(i, j, pixel.count);
+            pixel.add(self.sample(i, j, pixel.count, world, &mut rng, tally));
+            if pixel.count >= min_samples && pixel.error() < variance_threshold {
+                break;
+            }
+        }
+
+        self.output_value(pixel.mean(), pixel.count)
+    }
+
+    /// The value output for a pixel with the given color and number of samples: the color
+    /// itself, or its place on the heatmap.
+    fn output_value(&self, pixel_color: Color, samples: i32) -> Color {
+        if !self.heatmap {
+            return pixel_color;
+        }
+
+        heat(samples as f64 / self.samples_per_pixel as f64)
+    }
+
+    /// Returns a progress bar over `len` units of work, or a hidden one if progress output is
+    /// off. The bar is shared by reference between the rayon workers, it synchronizes
+    /// internally.
+    fn progress_bar(&self, unit: &str, len: u64) -> ProgressBar {
+        if !self.progress {
+            return ProgressBar::hidden();
+        }
+
+        ProgressBar::new(len).with_style(
+            ProgressStyle::with_template(&format!(
+                "{unit} {{pos}}/{{len}} {{wide_bar}} ETA {{eta}}"
+            ))
+            .expect("progress template is valid"),
+        )
+    }
+
+    /// Renders the image progressively according to the camera's `RefinementStrategy`, calling
+    /// `on_pass` with a full-resolution preview after every pass, and returns the final image.
+    ///
+    /// Buffers are row-major, starting from the top-left pixel of the render region. With
+    /// `Uniform` refinement there is a single pass.
+    pub fn render_progressive(
+        &self,
+        world: &impl Hittable,
+        mut on_pass: impl FnMut(&[Color]),
+    ) -> Vec<Color> {
+        let RefinementStrategy::Progressive { initial_stride } = self.refinement else {
+            let image = self.render_tiles(world);
+            on_pass(&image);
+            return image;
+        };
+
+        // Pixels are indexed within the region, and offset by its corner when sampled.
+        let (x0, y0, _, _) = self.region;
+        let (width, height) = self.output_size();
+        let width = width as usize;
+        let pixel_count = width * height as usize;
+        let mut stats = vec![PixelStats::default(); pixel_count];
+
+        // Coarse-to-fine passes: each pass samples the pixels on a grid twice as dense as the
+        // previous one, and every other pixel shows the nearest sampled pixel above and left.
+        let initial_samples = (self.samples_per_pixel / 4).max(2);
+        let mut stride = initial_stride.max(1) as usize;
+        loop {
+            self.for_each_row(&mut stats, width, |row, pixels, tally| {
+                if row % stride != 0 || self.cancelled() {
+                    return;
+                }
+                let j = y0 + row as i32;
+                for (i, pixel) in pixels.iter_mut().enumerate().step_by(stride) {
+                    let i = x0 + i as i32;
+                    while pixel.count < initial_samples {
+                        self.add_sample(pixel, i, j, world, tally);
+                    }
+                }
+            });
+
+            let preview: Vec<Color> = (0..pixel_count)
+                .map(|idx| {
+                    let (i, j) = (idx % width, idx / width);
+                    let anchor = &stats[(j - j % stride) * width + i - i % stride];
+                    self.output_value(anchor.mean(), anchor.count)
+                })
+                .collect();
+            on_pass(&preview);
+
+            if stride == 1 || self.cancelled() {
+                break;
+            }
+            stride /= 2;
+        }
+
+        // Variance-guided passes: the noisiest quarter of the pixels get more samples until the
+        // budget of `samples_per_pixel` per pixel on average is used up.
+        let batch = (self.samples_per_pixel / 8).max(1);
+        let mut remaining =
+            pixel_count as i64 * (self.samples_per_pixel - initial_samples).max(0) as i64;
+        let mut order: Vec<usize> = (0..pixel_count).collect();
+        let mut selected = vec![false; pixel_count];
+        while remaining > 0 && !self.cancelled() {
+            order.sort_unstable_by(|&a, &b| stats[b].error().total_cmp(&stats[a].error()));
+
+            let batch_pixels = (remaining + batch as i64 - 1) / batch as i64;
+            let chosen = (pixel_count.div_ceil(4) as i64).min(batch_pixels);
+            selected.fill(false);
+            for &idx in &order[..chosen as usize] {
+                selected[idx] = true;
+            }
+            remaining -= chosen * batch as i64;
+
+            self.for_each_row(&mut stats, width, |row, pixels, tally| {
+                let j = y0 + row as i32;
+                for (i, pixel) in pixels.iter_mut().enumerate() {
+                    if !selected[row * width + i] || self.cancelled() {
+                        continue;
+                    }
+                    for _ in 0..batch {
+                        self.add_sample(pixel, x0 + i as i32, j, world, tally);
+                    }
                 }
+            });
+
+            let preview: Vec<Color> = stats
+                .iter()
+                .map(|pixel| self.output_value(pixel.mean(), pixel.count))
+                .collect();
+            on_pass(&preview);
+        }
+
+        stats
+            .iter()
+            .map(|pixel| self.output_value(pixel.mean(), pixel.count))
+            .collect()
+    }
+
+    /// Calls `f` with the index, the pixels and a tally of every row of `buffer`, which holds
+    /// rows of `width` pixels, on all cores when rendering in parallel. The tallies are added to
+    /// the camera's counters.
+    fn for_each_row<T: Send>(
+        &self,
+        buffer: &mut [T],
+        width: usize,
+        f: impl Fn(usize, &mut [T], &mut Tally) + Sync,
+    ) {
+        let render_row = |(row, pixels): (usize, &mut [T])| {
+            let mut tally = Tally::default();
+            f(row, pixels, &mut tally);
+            self.counters.add(&tally);
+        };
+        if self.parallel {
+            buffer
+                .par_chunks_mut(width)
+                .enumerate()
+                .for_each(render_row);
+        } else {
+            buffer.chunks_mut(width).enumerate().for_each(render_row);
+        }
+    }
+
+    /// Traces the next sample of pixel i, j and adds it to `pixel`.
+    fn add_sample(
+        &self,
+        pixel: &mut PixelStats,
+        i: i32,
+        j: i32,
+        world: &impl Hittable,
+        tally: &mut Tally,
+    ) {
+        let mut rng = self.sample_rng(i, j, pixel.count);
+        pixel.add(self.sample(i, j, pixel.count, world, &mut rng, tally));
+    }
 
-                color::write_color(self.pixel_samples_scale * pixel_color);
+    /// Traces the s-th sample of pixel i, j.
+    fn sample(
+        &self,
+        i: i32,
+        j: i32,
+        s: i32,
+        world: &impl Hittable,
+        rng: &mut dyn RngCore,
+        tally: &mut Tally,
+    ) -> Color {
+        tally.samples += 1;
+        tally.primary_rays += 1;
+        let r = self.get_ray(i, j, s, rng);
+        if self.debug != DebugMode::Off {
+            return self.debug_color(&r, world, rng, tally);
+        }
+        let sample = self.ray_color(&r, world, rng, tally);
+
+        match self.clamp_luminance {
+            Some(max_luminance) if color::luminance(sample) > max_luminance => {
+                sample * (max_luminance / color::luminance(sample))
             }
+            _ => sample,
         }
     }
 
-    fn get_ray(&self, i: i32, j: i32) -> Ray {
-        // Construct a camera ray originating from the defocus disk and directed at a randomly
+    /// Colors the first hit of `r` according to the camera's `DebugMode`.
+    fn debug_color(
+        &self,
+        r: &Ray,
+        world: &impl Hittable,
+        rng: &mut dyn RngCore,
+        tally: &mut Tally,
+    ) -> Color {
+        tally.rays += 1;
+        let ray_t = Interval::new(self.shadow_bias, f64::INFINITY);
+        let mut nodes_visited = 0;
+        let hit = match self.debug {
+            DebugMode::BvhNodes { .. } => world.hit_counting(r, ray_t, &mut nodes_visited),
+            _ => world.hit(r, ray_t),
+        };
+
+        match (self.debug, hit) {
+            (DebugMode::BvhNodes { max_nodes }, _) => {
+                heat(nodes_visited as f64 / max_nodes.max(1) as f64)
+            }
+            (DebugMode::AmbientOcclusion(_), None) => Color::ONE,
+            (_, None) => Color::ZERO,
+            (DebugMode::AmbientOcclusion(ao), Some(rec)) => {
+                // The fraction of the rays that get away without hitting anything nearby.
+                let uvw = Onb::new(rec.normal);
+                let samples = ao.samples.max(1);
+                let unoccluded = (0..samples)
+                    .filter(|_| {
+                        tally.rays += 1;
+                        let probe = Ray::new(rec.p, uvw.transform(random_cosine_direction(rng)))
+                            .with_kind(RayKind::Shadow)
+                            .with_time(r.time);
+                        world
+                            .hit(&probe, Interval::new(self.shadow_bias, ao.max_distance))
+                            .is_none()
+                    })
+                    .count();
+                Color::splat(unoccluded as f64 / samples as f64)
+            }
+            (DebugMode::Normals, Some(rec)) => {
+                let outward_normal = if rec.front_face {
+                    rec.normal
+                } else {
+                    -rec.normal
+                };
+                0.5 * (outward_normal + Color::ONE)
+            }
+            (DebugMode::Depth { max_distance }, Some(rec)) => {
+                let distance = rec.t * r.length_squared().sqrt();
+                Color::splat(1.0 - (distance / max_distance).clamp(0.0, 1.0))
+            }
+            (DebugMode::Off, Some(_)) => unreachable!("debug colors are only used when debugging"),
+        }
+    }
+
+    /// Returns the camera ray through the center of pixel i, j from the center of the camera,
+    /// at the middle of the pixel's exposure.
+    fn center_ray(&self, i: i32, j: i32) -> Ray {
+        let pixel_center =
+            self.pixel00_loc + i as f64 * self.pixel_delta_u + j as f64 * self.pixel_delta_v;
+        let (ray_origin, ray_direction) = match self.projection {
+            Projection::Orthographic { .. } => (pixel_center, self.forward),
+            Projection::Perspective => (self.center, pixel_center - self.center),
+        };
+
+        let ray_time = match self.shutter {
+            Shutter::Global => 0.5,
+            Shutter::Rolling { duration } => {
+                let duration = duration.clamp(0.0, 1.0);
+                (j as f64 / self.image_height as f64) * (1.0 - duration) + 0.5 * duration
+            }
+        };
+
+        Ray::new(ray_origin, ray_direction)
+            .with_kind(RayKind::Primary)
+            .with_time(ray_time)
+    }
+
+    /// Returns the random number generator for the given stream of the camera's seed.
+    fn rng(&self, stream: u64) -> StdRng {
+        StdRng::seed_from_u64(self.seed ^ stream.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+    }
+
+    /// Returns the generator for the s-th sample of pixel i, j. Every sample gets its own, so the
+    /// result doesn't depend on which thread renders it, in what order, or in which pass.
+    fn sample_rng(&self, i: i32, j: i32, s: i32) -> StdRng {
+        // Widened before multiplying, images of 2^31 pixels or more would overflow an i32.
+        let pixel = j as u64 * self.image_width as u64 + i as u64 + 1;
+        self.rng(pixel | (s as u64) << 32)
+    }
+
+    fn get_ray(&self, i: i32, j: i32, s: i32, rng: &mut dyn RngCore) -> Ray {
+        // Construct a camera ray originating from the defocus disk and directed at the s-th
         // sampled point around the pixel location i, j.
 
-        let offset = Self::sample_square();
+        let offset = match self.pixel_sampling {
+            PixelSampling::LowDiscrepancy => self.sample_square(i, j, s),
+            PixelSampling::Stratified => {
+                // Samples past the grid, e.g. in progressive refinement, start over at the top.
+                let cell = s % (self.sqrt_spp * self.sqrt_spp);
+                self.sample_square_stratified(cell % self.sqrt_spp, cell / self.sqrt_spp, rng)
+            }
+        };
         let pixel_sample = self.pixel00_loc
             + (i as f64 + offset.x) * self.pixel_delta_u
             + (j as f64 + offset.y) * self.pixel_delta_v;
 
-        let ray_origin = if self.defocus_angle <= 0.0 {
-            self.center
-        } else {
-            self.defocus_disk_sample()
+        let (ray_origin, ray_direction) = match self.projection {
+            Projection::Orthographic { .. } => (pixel_sample, self.forward),
+            Projection::Perspective => {
+                let ray_origin = if self.defocus_angle <= 0.0 {
+                    self.center
+                } else {
+                    self.defocus_disk_sample(rng)
+                };
+                (ray_origin, pixel_sample - ray_origin)
+            }
+        };
+
+        let ray_time = match self.shutter {
+            Shutter::Global => rng.gen(),
+            Shutter::Rolling { duration } => {
+                // Rows start exposing one after the other, from the top of the image down.
+                let duration = duration.clamp(0.0, 1.0);
+                let row_start = (j as f64 / self.image_height as f64) * (1.0 - duration);
+                row_start + duration * rng.gen::<f64>()
+            }
         };
-        let ray_direction = pixel_sample - ray_origin;
 
         Ray::new(ray_origin, ray_direction)
+            .with_kind(RayKind::Primary)
+            .with_time(ray_time)
+    }
+
+    /// Follows a path from `r` through the world for up to `max_depth` bounces and returns the
+    /// light it carries back. The light emitted and scattered at every bounce is weighed by the
+    /// product of the attenuations of all the bounces before it.
+    ///
+    /// With `lights` set, diffuse bounces also sample a point on the lights and add its light
+    /// directly if nothing is in the way (next-event estimation). Light a path reaches both
+    /// ways is weighed with multiple importance sampling, so it isn't counted twice.
+    fn ray_color(
+        &self,
+        r: &Ray,
+        world: &impl Hittable,
+        rng: &mut dyn RngCore,
+        tally: &mut Tally,
+    ) -> Color {
+        self.continue_path(r, PathState::START, None, world, rng, tally)
+    }
+
+    /// Follows a path like `ray_color`, picking up at the bounce `state` is at. With `first_hit`,
+    /// that bounce goes on from where `r` was already found to hit, without tracing it again.
+    fn continue_path(
+        &self,
+        r: &Ray,
+        state: PathState,
+        mut first_hit: Option<HitRecord>,
+        world: &impl Hittable,
+        rng: &mut dyn RngCore,
+        tally: &mut Tally,
+    ) -> Color {
+        let PathState {
+            mut attenuation,
+            bounce: first_bounce,
+            mut last_pdfs,
+            mut last_background_pdf,
+            mut caustic,
+        } = state;
+        let mut color = Color::ZERO;
+        let mut current_ray = *r;
+
+        for bounce in first_bounce..self.max_depth {
+            let hit = match first_hit.take() {
+                // The roulette was played and the ray traced before the path was split.
+                Some(rec) => Some(rec),
+                None => {
+                    // Russian roulette: past the first few bounces, end dim paths at random
+                    // instead of following them to `max_depth`. Surviving paths are brightened
+                    // by the same odds, which keeps the image unbiased.
+                    if bounce >= ROULETTE_START_DEPTH {
+                        let survival = attenuation.max_element().min(1.0);
+                        if rng.gen::<f64>() >= survival {
+                            return color;
+                        }
+                        attenuation /= survival;
+                    }
+
+                    tally.rays += 1;
+                    tally.peak_depth = tally.peak_depth.max(bounce);
+
+                    world.hit(&current_ray, Interval::new(self.shadow_bias, f64::INFINITY))
+                }
+            };
+            let Some(rec) = hit else {
+                let d = current_ray.direction();
+                let background = match (&self.background, last_background_pdf) {
+                    (Background::Sky(sky), Some(scattering_pdf)) => {
+                        let weight = power_heuristic(scattering_pdf, sky.sun_pdf(d));
+                        sky.sky(d) + sky.sun(d) * weight
+                    }
+                    (Background::Environment(env), Some(scattering_pdf)) => {
+                        env.value(d) * power_heuristic(scattering_pdf, env.pdf(d))
+                    }
+                    _ => self.background_color(&current_ray),
+                };
+                return color + attenuation * background;
+            };
+
+            let mat = rec.mat.as_ref();
+
+            // Split the path into one per color channel, each going on from this hit at the
+            // wavelength of its channel.
+            if self.spectral && current_ray.wavelength.is_none() && mat.is_dispersive() {
+                let state = PathState {
+                    attenuation,
+                    bounce,
+                    last_pdfs,
+                    last_background_pdf,
+                    caustic,
+                };
+                for (wavelength, channel) in SPECTRAL_BANDS {
+                    let band_ray = current_ray.with_wavelength(Some(wavelength));
+                    let band_state = PathState {
+                        attenuation: attenuation * channel,
+                        ..state
+                    };
+                    if band_state.attenuation != Color::ZERO {
+                        let rec = Some(rec.clone());
+                        color += self.continue_path(&band_ray, band_state, rec, world, rng, tally);
+                    }
+                }
+                return color;
+            }
+
+            let emitted = mat.emitted(&current_ray, &rec);
+            let weight = match last_pdfs {
+                _ if caustic == CausticPath::Specular => 0.0,
+                Some((scattering_pdf, light_pdf)) => power_heuristic(scattering_pdf, light_pdf),
+                None => 1.0,
+            };
+            color += attenuation * emitted * weight;
+            last_pdfs = None;
+            last_background_pdf = None;
+
+            let Some(srec) = mat.scatter(&current_ray, &rec, rng) else {
+                return color;
+            };
+
+            let scattered = match srec.scattered {
+                Scattered::Ray(scattered) => {
+                    attenuation *= srec.attenuation;
+                    if caustic != CausticPath::No {
+                        caustic = CausticPath::Specular;
+                    }
+                    scattered
+                }
+                Scattered::Pdf(pdf) => {
+                    caustic = if bounce == 0 && self.splats_caustics {
+                        CausticPath::Diffuse
+                    } else {
+                        CausticPath::No
+                    };
+                    if let Some(lights) = &self.lights {
+                        tally.rays += 1;
+                        color += attenuation
+                            * self.direct_light(
+                                &current_ray,
+                                &rec,
+                                &srec.attenuation,
+                                pdf.as_ref(),
+                                lights.as_ref(),
+                                world,
+                                rng,
+                            );
+                    }
+
+                    if let Background::Sky(_) | Background::Environment(_) = &self.background {
+                        tally.rays += 1;
+                        color += attenuation
+                            * self.direct_background(
+                                &current_ray,
+                                &rec,
+                                &srec.attenuation,
+                                pdf.as_ref(),
+                                world,
+                                rng,
+                            );
+                    }
+
+                    let direction = pdf.generate(rng);
+                    let pdf_value = pdf.value(direction);
+                    if pdf_value <= 0.0 {
+                        return color;
+                    }
+
+                    let scattered = Ray::new(rec.p, direction).with_time(current_ray.time);
+                    let scattering_pdf = mat.scattering_pdf(&current_ray, &rec, &scattered);
+                    attenuation *= srec.attenuation * scattering_pdf / pdf_value;
+                    if let Some(lights) = &self.lights {
+                        last_pdfs = Some((
+                            pdf_value,
+                            lights.pdf_value(rec.p, direction, self.shadow_bias),
+                        ));
+                    }
+                    if let Background::Sky(_) | Background::Environment(_) = &self.background {
+                        last_background_pdf = Some(pdf_value);
+                    }
+                    scattered
+                }
+            };
+            current_ray = scattered.with_wavelength(current_ray.wavelength);
+            tally.bounces += 1;
+
+            // A degenerate direction, e.g. from normalizing a zero-length vector, poisons the
+            // rest of the path with NaNs. Dropping the sample is less visible than the speckle
+            // it would leave.
+            let finite = color.is_finite() && attenuation.is_finite();
+            if !finite || !current_ray.direction().is_finite() {
+                return Color::ZERO;
+            }
+        }
+
+        tally.truncated_paths += 1;
+        color
     }
 
-    fn ray_color(r: &Ray, depth: i32, world: &impl Hittable) -> Color {
-        if depth <= 0 {
+    /// Samples a direction towards `lights` from the diffuse bounce `rec`, and returns the light
+    /// arriving from there times the bounce's attenuation, weighed against the chance that
+    /// `pdf` scatters the path there instead.
+    #[allow(clippy::too_many_arguments)]
+    fn direct_light(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &Color,
+        pdf: &dyn Pdf,
+        lights: &dyn Hittable,
+        world: &impl Hittable,
+        rng: &mut dyn RngCore,
+    ) -> Color {
+        let direction = lights.random(rec.p, rng);
+        let light_pdf = lights.pdf_value(rec.p, direction, self.shadow_bias);
+        if light_pdf <= 0.0 {
             return Color::ZERO;
         }
 
-        if let Some(rec) = world.hit(r, Interval::new(0.001, f64::INFINITY)) {
-            let mat = rec.mat.as_ref();
+        // Whatever the shadow ray hits first is what lights the point from there, which is
+        // nothing if it isn't emissive.
+        let shadow = Ray::new(rec.p, direction)
+            .with_kind(RayKind::Shadow)
+            .with_time(r_in.time);
+        let Some(light_rec) = world.hit(&shadow, Interval::new(self.shadow_bias, f64::INFINITY))
+        else {
+            return Color::ZERO;
+        };
+        let emitted = light_rec.mat.emitted(&shadow, &light_rec);
+        if emitted == Color::ZERO {
+            return Color::ZERO;
+        }
 
-            if let Some((scattered, attenuation)) = mat.scatter(r, &rec) {
-                return attenuation * Self::ray_color(&scattered, depth - 1, world);
+        let scattering_pdf = rec.mat.scattering_pdf(r_in, rec, &shadow);
+        let weight = power_heuristic(light_pdf, pdf.value(direction));
+
+        *attenuation * scattering_pdf * emitted * weight / light_pdf
+    }
+
+    /// Samples a direction towards the sun of a sky, or the bright parts of an environment map,
+    /// from the diffuse bounce `rec`, and returns the light arriving from there times the
+    /// bounce's attenuation, weighed against the chance that `pdf` scatters the path there
+    /// instead.
+    fn direct_background(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &Color,
+        pdf: &dyn Pdf,
+        world: &impl Hittable,
+        rng: &mut dyn RngCore,
+    ) -> Color {
+        let (direction, background_pdf, light) = match &self.background {
+            Background::Sky(sky) => {
+                let direction = sky.sample_sun(rng);
+                (direction, sky.sun_pdf(direction), sky.sun(direction))
+            }
+            Background::Environment(env) => {
+                let direction = env.sample(rng);
+                (direction, env.pdf(direction), env.value(direction))
             }
-            let direction = rec.normal + random_vec3_unit();
-            return 0.5 * Self::ray_color(&Ray::new(rec.p, direction), depth - 1, world);
+            Background::Solid(_) | Background::Gradient(..) => return Color::ZERO,
+        };
+        if background_pdf <= 0.0 {
+            return Color::ZERO;
+        }
+
+        let shadow = Ray::new(rec.p, direction)
+            .with_kind(RayKind::Shadow)
+            .with_time(r_in.time);
+        if world
+            .hit(&shadow, Interval::new(self.shadow_bias, f64::INFINITY))
+            .is_some()
+        {
+            return Color::ZERO;
         }
 
-        let unit_direction = r.direction.normalize();
-        let a = 0.5 * (unit_direction.y + 1.0);
+        let scattering_pdf = rec.mat.scattering_pdf(r_in, rec, &shadow);
+        let weight = power_heuristic(background_pdf, pdf.value(direction));
 
-        (1.0 - a) * Color::splat(1.) + a * Color::new(0.5, 0.7, 1.0)
+        *attenuation * scattering_pdf * light * weight / background_pdf
     }
 
-    /// Returns the vector to a random point in the [-.5,-.5]-[+.5,+.5] unit square.
-    fn sample_square() -> Vec3 {
-        vec3(
-            rand::random::<f64>() - 0.5,
-            rand::random::<f64>() - 0.5,
-            0.0,
-        )
+    /// Whether the caustics pass runs, see `CameraParams::light_paths_per_pixel`.
+    fn traces_caustics(&self) -> bool {
+        self.light_paths_per_pixel > 0
+            && self.lights.is_some()
+            && self.debug == DebugMode::Off
+            && !self.heatmap
     }
 
-    fn defocus_disk_sample(&self) -> Vec3 {
-        let p = random_vec3_on_unit_disc();
+    /// Traces `light_paths_per_pixel` paths per pixel from the lights, and returns the light
+    /// they carry through glass and mirrors to the diffuse surfaces the camera sees, in the
+    /// layout of `render_to_buffer`.
+    fn render_caustics(&self, world: &impl Hittable, lights: &dyn Hittable) -> Vec<Color> {
+        let (width, height) = self.output_size();
+        let paths_per_batch = width.max(1) as usize * self.light_paths_per_pixel as usize;
+        let path_count = (height as usize * paths_per_batch) as f64;
+
+        // Every batch gets its own generator, so the result doesn't depend on the threads.
+        let trace_batch = |batch: i32| {
+            let mut rng = self.rng((batch as u64) << 32);
+            let mut tally = Tally::default();
+            let mut splats = vec![];
+            for _ in 0..paths_per_batch {
+                self.trace_light_path(world, lights, &mut rng, &mut tally, &mut splats);
+            }
+            self.counters.add(&tally);
+
+            splats
+        };
+        let batches: Vec<Vec<(usize, Color)>> = if self.parallel {
+            (0..height).into_par_iter().map(trace_batch).collect()
+        } else {
+            (0..height).map(trace_batch).collect()
+        };
+
+        let mut image = vec![Color::ZERO; (width * height) as usize];
+        for (idx, light) in batches.into_iter().flatten() {
+            image[idx] += light / path_count;
+        }
+
+        image
+    }
+
+    /// Traces one path from a random point on `lights` through specular bounces, and adds the
+    /// light it brings to the first diffuse surface after them, if the camera sees it, to
+    /// `splats` with the index of the pixel it's seen in.
+    fn trace_light_path(
+        &self,
+        world: &impl Hittable,
+        lights: &dyn Hittable,
+        rng: &mut StdRng,
+        tally: &mut Tally,
+        splats: &mut Vec<(usize, Color)>,
+    ) {
+        let time = rng.gen();
+        let Some((light_rec, area_pdf)) = lights.sample_surface(time, rng) else {
+            return;
+        };
+
+        // Leave the light in a cosine-weighted direction, which cancels the cosine of the light
+        // leaving the surface out of the power the path carries.
+        let direction = Onb::new(light_rec.normal).transform(random_cosine_direction(rng));
+        let towards_light = Ray::new(light_rec.p + direction, -direction).with_time(time);
+        let mut power = light_rec.mat.emitted(&towards_light, &light_rec) * PI / area_pdf;
+        let mut r = Ray::new(light_rec.p, direction).with_time(time);
+        if self.spectral {
+            let (wavelength, channel) = SPECTRAL_BANDS[rng.gen_range(0..SPECTRAL_BANDS.len())];
+            power *= SPECTRAL_BANDS.len() as f64 * channel;
+            r = r.with_wavelength(Some(wavelength));
+        }
+
+        let mut specular = false;
+        for _ in 0..self.max_depth {
+            if power == Color::ZERO || !power.is_finite() {
+                return;
+            }
+
+            tally.rays += 1;
+            let Some(rec) = world.hit(&r, Interval::new(self.shadow_bias, f64::INFINITY)) else {
+                return;
+            };
+            let Some(srec) = rec.mat.scatter(&r, &rec, rng) else {
+                return;
+            };
+            match srec.scattered {
+                Scattered::Ray(scattered) => {
+                    power *= srec.attenuation;
+                    r = scattered.with_wavelength(r.wavelength);
+                    specular = true;
+                }
+                // Light reaching diffuse surfaces without passing through glass or mirrors is
+                // left to the camera paths, which sample the lights directly.
+                Scattered::Pdf(_) if !specular => return,
+                Scattered::Pdf(_) => {
+                    tally.rays += 1;
+                    if let Some(splat) = self.splat_on_camera(&r, &rec, srec.attenuation, world) {
+                        splats.push((splat.0, power * splat.1));
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Returns the pixel index the camera sees the diffuse hit `rec` of a light path in, and how
+    /// much of the power the path arrived with along `r_in` the pixel shows, if any.
+    fn splat_on_camera(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: Color,
+        world: &impl Hittable,
+    ) -> Option<(usize, Color)> {
+        let (pixel, depth) = self.project(rec.p)?;
+        let (i, j) = (
+            (pixel.x + 0.5).floor() as i32,
+            (pixel.y + 0.5).floor() as i32,
+        );
+        let (x0, y0, x1, y1) = self.region;
+        if !(x0..x1).contains(&i) || !(y0..y1).contains(&j) {
+            return None;
+        }
+
+        // How much of the image plane, in pixels, a unit of area around `rec.p` facing the
+        // camera covers.
+        let pixel_area = self.pixel_delta_u.cross(self.pixel_delta_v).length();
+        let (to_camera, distance, pixels_per_area) = match self.projection {
+            Projection::Perspective => {
+                let to_camera = (self.center - rec.p) / depth;
+                let focal_length = (self.pixel00_loc - self.center).dot(self.forward);
+                let cos_camera = -to_camera.dot(self.forward);
+                let scale = focal_length * focal_length / (cos_camera.powi(3) * depth * depth);
+                (to_camera, depth, scale / pixel_area)
+            }
+            Projection::Orthographic { .. } => (-self.forward, depth, 1.0 / pixel_area),
+        };
+
+        let shadow = Ray::new(rec.p, to_camera)
+            .with_kind(RayKind::Shadow)
+            .with_time(r_in.time);
+        let ray_t = Interval::new(self.shadow_bias, distance - self.shadow_bias);
+        if world.hit(&shadow, ray_t).is_some() {
+            return None;
+        }
+
+        // The material scatters light alike both ways, so light arriving along `r_in` leaves
+        // towards the camera as a camera path along `shadow` would leave towards `r_in`.
+        let scattering_pdf = rec.mat.scattering_pdf(r_in, rec, &shadow);
+        let idx = ((j - y0) * (x1 - x0) + (i - x0)) as usize;
+
+        Some((idx, attenuation * scattering_pdf * pixels_per_area))
+    }
+
+    fn background_color(&self, r: &Ray) -> Color {
+        match &self.background {
+            Background::Solid(color) => *color,
+            Background::Gradient(bottom, top) => {
+                let unit_direction = r.direction().normalize();
+                let a = 0.5 * (unit_direction.y + 1.0);
+
+                (1.0 - a) * *bottom + a * *top
+            }
+            Background::Environment(env) => env.value(r.direction()),
+            Background::Sky(sky) => sky.value(r.direction()),
+        }
+    }
+
+    /// Returns the vector to the s-th sample point of pixel i, j in the [-.5,-.5]-[+.5,+.5] unit
+    /// square. The low-discrepancy sequence is shifted per pixel by the blue-noise mask so that
+    /// neighboring pixels don't share the same pattern.
+    fn sample_square(&self, i: i32, j: i32, s: i32) -> Vec3 {
+        let (x, y) = sampling::r2(s as usize);
+        let (dx, dy) = self.blue_noise.offset(i, j);
+
+        vec3((x + dx).fract() - 0.5, (y + dy).fract() - 0.5, 0.0)
+    }
+
+    /// Returns the vector to a random point in the grid cell `s_i`, `s_j` of the
+    /// [-.5,-.5]-[+.5,+.5] unit square, which is divided into `sqrt_spp` cells along each side.
+    fn sample_square_stratified(&self, s_i: i32, s_j: i32, rng: &mut dyn RngCore) -> Vec3 {
+        let px = ((s_i as f64 + rng.gen::<f64>()) * self.recip_sqrt_spp) - 0.5;
+        let py = ((s_j as f64 + rng.gen::<f64>()) * self.recip_sqrt_spp) - 0.5;
+
+        vec3(px, py, 0.0)
+    }
+
+    fn defocus_disk_sample(&self, rng: &mut dyn RngCore) -> Vec3 {
+        let p = random_vec3_on_unit_disc(rng);
 
         self.center + p.x * self.defocus_disk_u + p.y * self.defocus_disk_v
     }
 }
+
+/// Counters collected while rendering, see `RenderStats`.
+#[derive(Default)]
+struct Counters {
+    samples: AtomicU64,
+    rays: AtomicU64,
+    primary_rays: AtomicU64,
+    bounces: AtomicU64,
+    peak_depth: AtomicI32,
+    truncated_paths: AtomicU64,
+}
+
+impl Clone for Counters {
+    /// Counters only hold the stats of the render in progress, so a copy of the camera starts
+    /// from zero.
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl Counters {
+    fn add(&self, tally: &Tally) {
+        self.samples.fetch_add(tally.samples, Ordering::Relaxed);
+        self.rays.fetch_add(tally.rays, Ordering::Relaxed);
+        self.primary_rays
+            .fetch_add(tally.primary_rays, Ordering::Relaxed);
+        self.bounces.fetch_add(tally.bounces, Ordering::Relaxed);
+        self.peak_depth
+            .fetch_max(tally.peak_depth, Ordering::Relaxed);
+        self.truncated_paths
+            .fetch_add(tally.truncated_paths, Ordering::Relaxed);
+    }
+}
+
+/// Counters gathered by a single thread, merged into `Counters` in bulk so that threads don't
+/// contend on the atomics for every ray.
+#[derive(Default)]
+struct Tally {
+    samples: u64,
+    rays: u64,
+    primary_rays: u64,
+    bounces: u64,
+    peak_depth: i32,
+    truncated_paths: u64,
+}
+
+/// How far along its bounces a path traced by `Camera::continue_path` is.
+#[derive(Clone, Copy)]
+struct PathState {
+    /// Product of the attenuations of all the bounces so far
+    attenuation: Color,
+    bounce: i32,
+    /// Densities with which the scattering and the lights would have picked the direction of
+    /// the last diffuse bounce, to weigh light the path hits against what the lights sampled.
+    last_pdfs: Option<(f64, f64)>,
+    /// Density with which the last diffuse bounce picked its direction, when the sun of the sky
+    /// or the environment map was sampled there as well.
+    last_background_pdf: Option<f64>,
+    /// Whether the light of emissive surfaces the path hits is left to the caustics pass
+    caustic: CausticPath,
+}
+
+impl PathState {
+    /// A path leaving the camera.
+    const START: Self = Self {
+        attenuation: Color::ONE,
+        bounce: 0,
+        last_pdfs: None,
+        last_background_pdf: None,
+        caustic: CausticPath::No,
+    };
+}
+
+/// How far a camera path is along a diffuse surface seen by the camera followed by specular
+/// bounces, whose light from emissive surfaces the caustics pass adds instead, see
+/// `CameraParams::light_paths_per_pixel`.
+#[derive(Clone, Copy, PartialEq)]
+enum CausticPath {
+    No,
+    /// The camera ray hit a diffuse surface, with light tracing on.
+    Diffuse,
+    /// Only specular bounces since.
+    Specular,
+}
+
+/// Running mean of a pixel's samples, and the variance of their luminance.
+#[derive(Clone, Copy, Default)]
+struct PixelStats {
+    count: i32,
+    sum: Color,
+    mean_luminance: f64,
+    m2: f64,
+}
+
+impl PixelStats {
+    fn add(&mut self, sample: Color) {
+        // Welford's online variance algorithm.
+        let luminance = color::luminance(sample);
+        self.count += 1;
+        self.sum += sample;
+        let delta = luminance - self.mean_luminance;
+        self.mean_luminance += delta / self.count as f64;
+        self.m2 += delta * (luminance - self.mean_luminance);
+    }
+
+    fn mean(&self) -> Color {
+        if self.count == 0 {
+            return Color::ZERO;
+        }
+
+        self.sum / self.count as f64
+    }
+
+    /// Variance of the mean, i.e. the squared standard error.
+    fn error(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+
+        self.m2 / (self.count - 1) as f64 / self.count as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::{Dielectric, DiffuseLight, Lambertian},
+        shapes::InfinitePlane,
+        Sphere,
+    };
+
+    /// A diffuse sphere on a diffuse ground.
+    fn scene() -> HittableList {
+        let mat = Arc::new(Lambertian::new(Color::splat(0.5)));
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Point3::new(0.0, 0.0, -1.0),
+            0.5,
+            mat.clone(),
+        )));
+        world.add(Box::new(Sphere::new(
+            Point3::new(0.0, -100.5, -1.0),
+            100.0,
+            mat,
+        )));
+
+        world
+    }
+
+    fn camera() -> CameraBuilder {
+        Camera::builder()
+            .image_width(40)
+            .samples_per_pixel(4)
+            .max_depth(8)
+            .seed(3)
+            .progress(false)
+    }
+
+    #[test]
+    fn framing_leaves_out_infinite_planes() {
+        let mut world = scene();
+        world.remove(1);
+        world.add(Box::new(InfinitePlane {
+            point: Point3::new(0.0, -0.5, 0.0),
+            normal: Vec3::Y,
+            mat: Arc::new(Lambertian::new(Color::splat(0.5))),
+        }));
+
+        let mut params = camera().params();
+        params.frame_objects(&world);
+        assert_eq!(params.lookat, Point3::new(0.0, 0.0, -1.0));
+        assert!(params.focus_dist.is_finite());
+    }
+
+    #[test]
+    fn huge_images_seed_every_pixel_apart() {
+        let cam = camera().image_width(60_000).aspect_ratio(1.0).build();
+        let mut last = cam.sample_rng(59_999, 59_999, 0);
+        let mut first = cam.sample_rng(0, 0, 0);
+        assert_ne!(last.gen::<u64>(), first.gen::<u64>());
+    }
+
+    #[test]
+    fn pixels_are_square_for_any_aspect_ratio() {
+        for aspect_ratio in [1.0, 4.0 / 3.0, 9.0 / 16.0, 2.39] {
+            for image_width in [1, 7, 41, 99, 101] {
+                let cam = camera()
+                    .aspect_ratio(aspect_ratio)
+                    .image_width(image_width)
+                    .build();
+                let (du, dv) = (cam.pixel_delta_u.length(), cam.pixel_delta_v.length());
+                assert!(
+                    (du - dv).abs() < 1e-9 * du,
+                    "{image_width} px at {aspect_ratio} has {du} by {dv} pixels"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn tiled_render_matches_single_threaded_scanlines() {
+        let world = scene();
+
+        let mut scanlines = vec![];
+        camera()
+            .parallel(false)
+            .build()
+            .render_scanlines(&world, |_, row| scanlines.extend_from_slice(row));
+
+        for tile_size in [1, 7, 32] {
+            let tiled = camera()
+                .parallel(true)
+                .tile_size(tile_size)
+                .build()
+                .render_tiles(&world);
+            assert!(
+                tiled == scanlines,
+                "tiles of {tile_size} pixels render differently"
+            );
+        }
+        for tile_order in [TileOrder::Spiral, TileOrder::Hilbert, TileOrder::Random] {
+            let tiled = camera()
+                .tile_size(5)
+                .tile_order(tile_order)
+                .build()
+                .render_tiles(&world);
+            assert!(tiled == scanlines, "tile order changes the image");
+
+            let (passes, _) = camera()
+                .tile_size(5)
+                .tile_order(tile_order)
+                .build()
+                .render_image(&world);
+            assert!(passes == scanlines, "tiled passes render differently");
+        }
+    }
+
+    #[test]
+    fn pixel_centers_project_back_onto_their_pixel() {
+        let perspective = camera().build();
+        let orthographic = camera()
+            .projection(Projection::Orthographic { width: 4.0 })
+            .build();
+        for cam in [perspective, orthographic] {
+            for (i, j) in [(0, 0), (39, 0), (17, 11), (39, 21)] {
+                let r = cam.center_ray(i, j);
+                let (pixel, depth) = cam.project(r.at(3.0)).unwrap();
+                assert!(pixel.abs_diff_eq(DVec2::new(i as f64, j as f64), 1e-9));
+                assert!((depth - 3.0 * r.direction().length()).abs() < 1e-9);
+                assert!(cam.project(r.at(-3.0)).is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn motion_vectors_follow_moving_spheres() {
+        let cam = camera().build();
+        let mut world = HittableList::new();
+        let mat = Arc::new(Lambertian::new(Color::splat(0.5)));
+        world.add(Box::new(Sphere::moving(
+            Point3::new(-0.1, 0.0, -1.0),
+            Point3::new(0.1, 0.0, -1.0),
+            0.5,
+            mat,
+        )));
+
+        let motion = cam.render_motion_vectors(&world, &cam);
+        let (width, height) = cam.output_size();
+        let middle = motion[(height / 2 * width + width / 2) as usize].unwrap();
+        assert!(middle.offset.x > 0.0 && middle.offset.y.abs() < 1e-9);
+        assert!(motion[0].is_none());
+    }
+
+    /// A small light casting a caustic through a glass ball onto the ground beside it.
+    fn caustic_scene() -> (HittableList, CameraBuilder) {
+        let light = || {
+            let emit = Color::splat(40.0);
+            Sphere::new(vec3(-3.0, 3.0, 0.0), 0.2, Arc::new(DiffuseLight { emit }))
+        };
+        let mut world = HittableList::new();
+        let ground = Arc::new(Lambertian::new(Color::splat(0.7)));
+        world.add(Box::new(Sphere::new(
+            vec3(0.0, -1000.0, 0.0),
+            1000.0,
+            ground,
+        )));
+        let glass = Arc::new(Dielectric::new(1.5));
+        world.add(Box::new(Sphere::new(vec3(0.0, 1.0, 0.0), 1.0, glass)));
+        world.add(Box::new(light()));
+
+        let cam = camera()
+            .image_width(24)
+            .aspect_ratio(1.0)
+            .lookfrom(vec3(0.0, 4.0, 8.0))
+            .lookat(vec3(1.0, 0.5, 0.0))
+            .vfov(40.0)
+            .defocus_angle(0.0)
+            .background(Background::Solid(Color::ZERO))
+            .lights(Some(Arc::new(light())));
+        (world, cam)
+    }
+
+    #[test]
+    fn light_paths_add_caustics_without_counting_light_twice() {
+        let (world, cam) = caustic_scene();
+        let mean = |image: Vec<Color>| image.iter().sum::<Color>().x / image.len() as f64;
+
+        let path_traced = mean(
+            cam.clone()
+                .samples_per_pixel(256)
+                .build()
+                .render_image(&world)
+                .0,
+        );
+        let light_traced = cam.clone().samples_per_pixel(16).light_paths_per_pixel(64);
+        let parallel = light_traced.clone().build().render_image(&world).0;
+        let serial = light_traced.parallel(false).build().render_image(&world).0;
+
+        assert!(parallel == serial, "light paths depend on the threads");
+        let light_traced = mean(parallel);
+        assert!(
+            (light_traced - path_traced).abs() < 0.05 * path_traced,
+            "{light_traced} with light paths, {path_traced} without"
+        );
+    }
+
+    #[test]
+    fn passes_find_caustics_without_light_paths() {
+        let (world, cam) = caustic_scene();
+        let mean = |image: &[Color]| image.iter().sum::<Color>().x / image.len() as f64;
+        let accumulate = |cam: Camera| {
+            let mut buffer = vec![Color::ZERO; 24 * 24];
+            for pass in 0..256 {
+                cam.render_pass(&world, &mut buffer, pass);
+            }
+            buffer.iter().map(|&sum| sum / 256.0).collect::<Vec<_>>()
+        };
+
+        let cam = cam.samples_per_pixel(256);
+        let passes = accumulate(cam.clone().light_paths_per_pixel(64).build());
+        assert!(passes == accumulate(cam.clone().build()));
+        let (image, _) = cam.light_paths_per_pixel(64).build().render_image(&world);
+        assert!(
+            (mean(&passes) - mean(&image)).abs() < 0.05 * mean(&image),
+            "{} from passes, {} from render_image",
+            mean(&passes),
+            mean(&image)
+        );
+    }
+
+    #[test]
+    fn tile_orders_visit_every_tile_once() {
+        let (region, tile_size) = ((3, 2, 83, 61), 8);
+        let scanline: Vec<(i32, i32)> = (2..61)
+            .step_by(8)
+            .flat_map(|j| (3..83).step_by(8).map(move |i| (i, j)))
+            .collect();
+        let mut rng = StdRng::seed_from_u64(0);
+        for tile_order in [TileOrder::Spiral, TileOrder::Hilbert, TileOrder::Random] {
+            let mut tiles = scanline.clone();
+            tile_order.arrange(&mut tiles, region, tile_size, &mut rng);
+            assert!(tiles != scanline);
+            tiles.sort_by_key(|&(i, j)| (j, i));
+            assert!(tiles == scanline);
+        }
+
+        // 10 by 8 tiles, the middle one being the 5th of the 4th row.
+        let mut tiles = scanline.clone();
+        TileOrder::Spiral.arrange(&mut tiles, region, tile_size, &mut rng);
+        assert_eq!(tiles[0], (3 + 4 * 8, 2 + 3 * 8));
+    }
+
+    #[test]
+    fn hilbert_curve_moves_one_cell_at_a_time() {
+        let side = 16;
+        let mut cells: Vec<(u32, u32)> = (0..side)
+            .flat_map(|y| (0..side).map(move |x| (x, y)))
+            .collect();
+        cells.sort_by_key(|&(x, y)| hilbert_index(side, x, y));
+        assert_eq!(cells[0], (0, 0));
+        for pair in cells.windows(2) {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            assert_eq!(x0.abs_diff(x1) + y0.abs_diff(y1), 1, "{pair:?}");
+        }
+    }
+}
@@ -1,27 +1,140 @@
+use std::io::{self, Write};
+
 use glam::DVec3 as Vec3;
 
 use crate::Interval;
 
 pub type Color = Vec3;
 
-pub fn write_color(pixel_color: Color) {
+/// How linear colors above 1.0 are brought into displayable range before gamma correction.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ToneMap {
+    /// Clips every channel to 1.0, so bright highlights turn flat white.
+    #[default]
+    Clamp,
+    /// Maps each channel `c` to `c / (1 + c)`, which rolls off smoothly towards 1.0.
+    Reinhard,
+    /// Krzysztof Narkowicz's fit of the ACES filmic curve, with more contrast than Reinhard.
+    AcesFilmic,
+}
+
+impl ToneMap {
+    pub fn apply(self, c: Color) -> Color {
+        match self {
+            ToneMap::Clamp => c,
+            ToneMap::Reinhard => c / (Color::ONE + c),
+            ToneMap::AcesFilmic => (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14),
+        }
+    }
+}
+
+/// The transfer function that encodes linear colors for display.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GammaMode {
+    /// Raises each channel to `1 / gamma`.
+    Power(f64),
+    /// The piecewise sRGB curve, which is linear close to black.
+    Srgb,
+    /// Leaves colors linear, e.g. for compositing software that expects linear data.
+    Linear,
+}
+
+impl Default for GammaMode {
+    /// Gamma 2.0, i.e. the square root of each channel.
+    fn default() -> Self {
+        Self::Power(2.0)
+    }
+}
+
+impl GammaMode {
+    pub fn encode(self, linear_component: f64) -> f64 {
+        if linear_component <= 0.0 {
+            return 0.0;
+        }
+
+        match self {
+            GammaMode::Power(gamma) => linear_component.powf(gamma.recip()),
+            GammaMode::Srgb if linear_component <= 0.003_130_8 => 12.92 * linear_component,
+            GammaMode::Srgb => 1.055 * linear_component.powf(1.0 / 2.4) - 0.055,
+            GammaMode::Linear => linear_component,
+        }
+    }
+}
+
+/// Writes the color as a line of plain PPM to stdout. For more than a few pixels, use
+/// `write_color_to` with a buffered writer instead.
+pub fn write_color(pixel_color: Color, tone_map: ToneMap, gamma: GammaMode) {
+    write_color_to(&mut io::stdout().lock(), pixel_color, tone_map, gamma)
+        .expect("failed printing to stdout");
+}
+
+/// Writes the color as a line of plain PPM, e.g. `255 128 0`, to `out`.
+pub fn write_color_to(
+    out: &mut impl Write,
+    pixel_color: Color,
+    tone_map: ToneMap,
+    gamma: GammaMode,
+) -> io::Result<()> {
+    let [r, g, b] = to_rgb8(pixel_color, tone_map, gamma);
+
+    writeln!(out, "{r} {g} {b}")
+}
+
+/// Tone maps and gamma corrects a linear color, and quantizes it to 8 bits per channel.
+pub fn to_rgb8(pixel_color: Color, tone_map: ToneMap, gamma: GammaMode) -> [u8; 3] {
     let intensity = Interval::new(0.000, 0.999);
+    let pixel_color = tone_map.apply(pixel_color.max(Color::ZERO));
 
-    let r = linear_to_gamma(pixel_color.x);
-    let g = linear_to_gamma(pixel_color.y);
-    let b = linear_to_gamma(pixel_color.z);
+    let r = gamma.encode(pixel_color.x);
+    let g = gamma.encode(pixel_color.y);
+    let b = gamma.encode(pixel_color.z);
 
-    let r = (256.0 * intensity.clamp(r)) as i32;
-    let g = (256.0 * intensity.clamp(g)) as i32;
-    let b = (256.0 * intensity.clamp(b)) as i32;
+    let r = (256.0 * intensity.clamp(r)) as u8;
+    let g = (256.0 * intensity.clamp(g)) as u8;
+    let b = (256.0 * intensity.clamp(b)) as u8;
 
-    println!("{r} {g} {b}");
+    [r, g, b]
 }
 
-fn linear_to_gamma(linear_component: f64) -> f64 {
-    if linear_component > 0.0 {
-        return linear_component.sqrt();
+/// Returns the relative luminance of a linear color using the Rec.709 coefficients.
+pub fn luminance(c: Color) -> f64 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+/// Returns a gray color with the same luminance as `c` in every channel.
+pub fn to_grayscale(c: Color) -> Color {
+    Color::splat(luminance(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn luminance_of_primaries_and_white() {
+        assert!((luminance(Color::new(0.0, 1.0, 0.0)) - 0.7152).abs() < 1e-12);
+        assert!((luminance(Color::new(1.0, 0.0, 0.0)) - 0.2126).abs() < 1e-12);
+        assert!((luminance(Color::new(0.0, 0.0, 1.0)) - 0.0722).abs() < 1e-12);
+        assert!((luminance(Color::ONE) - 1.0).abs() < 1e-12);
+        assert_eq!(
+            to_grayscale(Color::ONE),
+            Color::splat(luminance(Color::ONE))
+        );
     }
 
-    0.0
+    #[test]
+    fn write_color_to_writes_ppm_lines() {
+        let mut out = Vec::new();
+        let gamma = GammaMode::default();
+        write_color_to(&mut out, Color::new(1.0, 0.25, 0.0), ToneMap::Clamp, gamma).unwrap();
+        write_color_to(
+            &mut out,
+            Color::new(2.0, -1.0, 0.0625),
+            ToneMap::Clamp,
+            gamma,
+        )
+        .unwrap();
+
+        assert_eq!(out, b"255 128 0\n255 0 64\n");
+    }
 }
@@ -0,0 +1,126 @@
+use crate::{aabb::Aabb, HitRecord, Hittable, Interval, Ray};
+
+/// Rays crossing a boundary more often than this, e.g. through a very bumpy mesh, miss the rest.
+const MAX_CROSSINGS: usize = 64;
+
+/// How the insides of the two operands of a `Csg` combine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CsgOp {
+    /// Inside either operand
+    Union,
+    /// Inside both operands
+    Intersection,
+    /// Inside `a` but not inside `b`, i.e. `b` carved out of `a`
+    Difference,
+}
+
+impl CsgOp {
+    fn inside(self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            CsgOp::Union => in_a || in_b,
+            CsgOp::Intersection => in_a && in_b,
+            CsgOp::Difference => in_a && !in_b,
+        }
+    }
+}
+
+/// Constructive solid geometry: the solid combining the insides of `a` and `b` according to
+/// `op`. Both operands must be closed, like spheres or boxes, so their insides are well defined.
+///
+/// Surfaces keep the material of the operand they come from. The inside of `b` carved out by a
+/// difference faces into the hole.
+pub struct Csg {
+    pub op: CsgOp,
+    pub a: Box<dyn Hittable>,
+    pub b: Box<dyn Hittable>,
+}
+
+impl Hittable for Csg {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        // Sweep along the ray through the boundaries of both operands, tracking whether the ray
+        // is inside each of them. The surfaces of the result are where that changes whether the
+        // ray is inside the result.
+        let mut crossings: Vec<(HitRecord, bool)> = Vec::new();
+        for (object, from_b) in [(&self.a, false), (&self.b, true)] {
+            for (enter, exit) in hit_spans(object.as_ref(), r) {
+                crossings.push((enter, from_b));
+                crossings.push((exit, from_b));
+            }
+        }
+        crossings.sort_by(|(a, _), (b, _)| a.t.total_cmp(&b.t));
+
+        let (mut in_a, mut in_b) = (false, false);
+        for (rec, from_b) in crossings {
+            let was_inside = self.op.inside(in_a, in_b);
+            if from_b {
+                in_b = !in_b;
+            } else {
+                in_a = !in_a;
+            }
+            let is_inside = self.op.inside(in_a, in_b);
+
+            if was_inside == is_inside || !ray_t.surrounds(rec.t) {
+                continue;
+            }
+
+            let mut outward_normal = if rec.front_face {
+                rec.normal
+            } else {
+                -rec.normal
+            };
+            if from_b && self.op == CsgOp::Difference {
+                outward_normal = -outward_normal;
+            }
+
+            let mut result = HitRecord::new(rec.p, rec.t, rec.mat, r, outward_normal);
+            (result.u, result.v, result.tangent) = (rec.u, rec.v, rec.tangent);
+            result.object_id = rec.object_id;
+            return Some(result);
+        }
+
+        None
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let a = self.a.bounding_box();
+        let b = self.b.bounding_box();
+
+        match self.op {
+            CsgOp::Union => a.union(&b),
+            CsgOp::Intersection => {
+                let overlap = |x: Interval, y: Interval| {
+                    let min = x.min.max(y.min);
+                    Interval::new(min, x.max.min(y.max).max(min))
+                };
+                Aabb::new(overlap(a.x, b.x), overlap(a.y, b.y), overlap(a.z, b.z))
+            }
+            CsgOp::Difference => a,
+        }
+    }
+
+    fn bvh_node_count(&self) -> u64 {
+        self.a.bvh_node_count() + self.b.bvh_node_count()
+    }
+}
+
+/// Returns the pairs of surfaces where the full line of `r` enters and exits the closed
+/// `object`, in order along the ray, including those behind its origin.
+pub fn hit_spans(object: &dyn Hittable, r: &Ray) -> Vec<(HitRecord, HitRecord)> {
+    const EPSILON: f64 = 0.0001;
+
+    let mut spans = Vec::new();
+    let mut search = Interval::UNIVERSE;
+    while spans.len() < MAX_CROSSINGS / 2 {
+        let Some(enter) = object.hit(r, search) else {
+            break;
+        };
+        let Some(exit) = object.hit(r, Interval::new(enter.t + EPSILON, f64::INFINITY)) else {
+            break;
+        };
+
+        search.min = exit.t + EPSILON;
+        spans.push((enter, exit));
+    }
+
+    spans
+}
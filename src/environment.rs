@@ -0,0 +1,300 @@
+use std::{f64::consts::PI, path::Path};
+
+use image::{DynamicImage, ImageResult};
+use rand::{Rng, RngCore};
+
+use crate::{
+    color::{self, Color},
+    pdf::Onb,
+    random_vec3_unit, Vec3,
+};
+
+/// An equirectangular image of the surroundings, seen by rays that don't hit anything.
+///
+/// Diffuse bounces also sample directions towards the map in proportion to its brightness, so
+/// small bright spots like the sun in an outdoor HDRI light the scene without much noise. The
+/// tables for that are built along with the map.
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    /// Linear colors, row-major from the top-left pixel
+    texels: Vec<Color>,
+    /// Cumulative distribution over the rows of how likely `sample` picks them, ending in 1.0
+    row_cdf: Vec<f64>,
+    /// Cumulative distribution over the texels of each row, row-major like `texels`
+    column_cdfs: Vec<f64>,
+    /// Probability of `sample` picking each texel, row-major like `texels`
+    texel_probabilities: Vec<f64>,
+}
+
+impl EnvironmentMap {
+    /// Loads a Radiance HDR (`.hdr`) image, or any other image the `image` crate can read, e.g. a
+    /// 16-bit PNG. Floating point images are taken as linear colors, others are assumed to be
+    /// gamma encoded like `ImageTexture`s.
+    pub fn load(path: impl AsRef<Path>) -> ImageResult<Self> {
+        let img = image::open(path)?;
+        let is_linear = matches!(
+            img,
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+        );
+        let img = img.into_rgb32f();
+
+        let texels = img
+            .pixels()
+            .map(|pixel| {
+                let [r, g, b] = pixel.0.map(|c| {
+                    let c = c as f64;
+                    if is_linear {
+                        c
+                    } else {
+                        c * c
+                    }
+                });
+                Color::new(r, g, b)
+            })
+            .collect();
+
+        Ok(Self::from_texels(
+            img.width() as usize,
+            img.height() as usize,
+            texels,
+        ))
+    }
+
+    /// Creates a map of `width` by `height` linear colors, row-major from the top-left pixel.
+    pub fn from_texels(width: usize, height: usize, texels: Vec<Color>) -> Self {
+        assert_eq!(
+            texels.len(),
+            width * height,
+            "texel count must match the size"
+        );
+
+        // Texels are picked by their brightness times the solid angle they cover, which shrinks
+        // towards the poles.
+        let weights: Vec<f64> = texels
+            .iter()
+            .enumerate()
+            .map(|(idx, &texel)| {
+                let theta = PI * ((idx / width) as f64 + 0.5) / height as f64;
+                color::luminance(texel).max(0.0) * theta.sin()
+            })
+            .collect();
+        let total: f64 = weights.iter().sum();
+
+        let mut row_cdf = Vec::with_capacity(height);
+        let mut column_cdfs = Vec::with_capacity(width * height);
+        let mut texel_probabilities = Vec::with_capacity(width * height);
+        let mut rows_so_far = 0.0;
+        for row in weights.chunks(width.max(1)) {
+            let row_total: f64 = row.iter().sum();
+            let mut columns_so_far = 0.0;
+            for &weight in row {
+                columns_so_far += weight;
+                column_cdfs.push(if row_total > 0.0 {
+                    columns_so_far / row_total
+                } else {
+                    1.0
+                });
+                texel_probabilities.push(if total > 0.0 { weight / total } else { 0.0 });
+            }
+
+            rows_so_far += row_total;
+            row_cdf.push(if total > 0.0 {
+                rows_so_far / total
+            } else {
+                1.0
+            });
+        }
+
+        Self {
+            width,
+            height,
+            texels,
+            row_cdf,
+            column_cdfs,
+            texel_probabilities,
+        }
+    }
+
+    /// Returns the color seen looking along `direction`, which doesn't need to be normalized.
+    ///
+    /// The image wraps around the y axis like the texture coordinates of a `Sphere`: its center
+    /// column looks along +x, and its top and bottom rows look straight up and down.
+    pub fn value(&self, direction: Vec3) -> Color {
+        if self.texels.is_empty() {
+            return Color::ZERO;
+        }
+
+        self.texels[self.texel_index(direction)]
+    }
+
+    /// Returns a random direction, more likely towards brighter parts of the map. Maps that are
+    /// black all over are sampled uniformly by area of the image, and maps without texels
+    /// uniformly over the sphere, though `pdf` gives 0 for them as they don't shine at all.
+    pub fn sample(&self, rng: &mut dyn RngCore) -> Vec3 {
+        if self.texels.is_empty() {
+            return random_vec3_unit(rng);
+        }
+
+        let pick = |cdf: &[f64], rng: &mut dyn RngCore| {
+            let x: f64 = rng.gen();
+            cdf.partition_point(|&c| c <= x).min(cdf.len() - 1)
+        };
+        let j = pick(&self.row_cdf, rng);
+        let i = pick(&self.column_cdfs[j * self.width..(j + 1) * self.width], rng);
+
+        // A uniformly random point within the texel.
+        let u = (i as f64 + rng.gen::<f64>()) / self.width as f64;
+        let v = (j as f64 + rng.gen::<f64>()) / self.height as f64;
+        let theta = (1.0 - v) * PI;
+        let phi = 2.0 * PI * u;
+        let sin_theta = theta.sin();
+
+        Vec3::new(-phi.cos() * sin_theta, -theta.cos(), phi.sin() * sin_theta)
+    }
+
+    /// Returns the density over directions with which `sample` picks `direction`, which doesn't
+    /// need to be normalized.
+    pub fn pdf(&self, direction: Vec3) -> f64 {
+        if self.texels.is_empty() {
+            return 0.0;
+        }
+
+        let sin_theta = (1.0 - direction.normalize().y.powi(2)).max(0.0).sqrt();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        // The texel's probability spread over its area of the unit square of texture
+        // coordinates, which maps onto 2π² sin(θ) of solid angle.
+        let probability = self.texel_probabilities[self.texel_index(direction)];
+        probability * (self.width * self.height) as f64 / (2.0 * PI * PI * sin_theta)
+    }
+
+    /// Returns the index of the texel seen looking along `direction`, see `value`.
+    fn texel_index(&self, direction: Vec3) -> usize {
+        let d = direction.normalize();
+        let theta = (-d.y).clamp(-1.0, 1.0).acos();
+        let phi = (-d.z).atan2(d.x) + PI;
+
+        let u = phi / (2.0 * PI);
+        let v = 1.0 - theta / PI;
+
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j = ((v * self.height as f64) as usize).min(self.height - 1);
+
+        j * self.width + i
+    }
+}
+
+/// A clear daylight sky with a sun, seen by rays that don't hit anything. The sky is brightest
+/// at the horizon and deepest blue at the zenith, and the sun is a sharp disk bright enough to
+/// light the scene like a directional light.
+#[derive(Clone, Debug)]
+pub struct ProceduralSky {
+    /// Direction towards the sun, which doesn't need to be normalized
+    pub sun_dir: Vec3,
+    /// Radiance of the sun disk. The light the sun adds to a surface facing it is this times
+    /// the solid angle of the disk, e.g. about 2.4e-4 for a radius of 0.5 degrees.
+    pub sun_intensity: f64,
+    /// Angular radius of the sun disk in degrees
+    pub sun_angular_radius: f64,
+    /// Haziness of the air, from 1.0 for a perfectly clear sky to 10.0 for a milky one. Haze
+    /// whitens the sky and spreads a glow around the sun.
+    pub turbidity: f64,
+}
+
+impl ProceduralSky {
+    /// Returns the color seen looking along `direction`, which doesn't need to be normalized:
+    /// the sky, plus the sun if the direction points at it.
+    pub fn value(&self, direction: Vec3) -> Color {
+        self.sky(direction) + self.sun(direction)
+    }
+
+    /// Returns the color of the sky alone looking along `direction`, without the sun disk.
+    pub fn sky(&self, direction: Vec3) -> Color {
+        let d = direction.normalize();
+        let sun_dir = self.sun_dir.normalize();
+        let haze = (self.turbidity.clamp(1.0, 10.0) - 1.0) / 9.0;
+
+        let zenith = Color::new(0.15, 0.35, 0.9).lerp(Color::new(0.6, 0.65, 0.7), haze);
+        let horizon = Color::new(0.75, 0.85, 1.0).lerp(Color::new(0.95, 0.95, 0.9), haze);
+        let ground = 0.4 * horizon;
+
+        let sky = if d.y >= 0.0 {
+            horizon.lerp(zenith, d.y.sqrt())
+        } else {
+            // Below the horizon the sky fades quickly into the haze over the ground.
+            horizon.lerp(ground, (-10.0 * d.y).min(1.0))
+        };
+
+        // Light scattered by haze glows around the sun, warmer when the sun is low.
+        let glow = (0.2 + 0.8 * haze) * d.dot(sun_dir).max(0.0).powi(32);
+
+        sky + glow * self.sun_tint()
+    }
+
+    /// Returns the radiance of the sun looking along `direction`, which doesn't need to be
+    /// normalized, or black if the direction misses the sun disk.
+    pub fn sun(&self, direction: Vec3) -> Color {
+        if direction.normalize().dot(self.sun_dir.normalize()) < self.cos_sun_radius() {
+            return Color::ZERO;
+        }
+
+        self.sun_intensity * self.sun_tint()
+    }
+
+    /// Returns a random direction towards the sun disk, uniformly distributed over its solid
+    /// angle.
+    pub fn sample_sun(&self, rng: &mut dyn RngCore) -> Vec3 {
+        let cos_max = self.cos_sun_radius();
+        let cos_theta = 1.0 - rng.gen::<f64>() * (1.0 - cos_max);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let phi = 2.0 * PI * rng.gen::<f64>();
+
+        Onb::new(self.sun_dir).transform(Vec3::new(
+            phi.cos() * sin_theta,
+            phi.sin() * sin_theta,
+            cos_theta,
+        ))
+    }
+
+    /// Returns the density with which `sample_sun` picks `direction`.
+    pub fn sun_pdf(&self, direction: Vec3) -> f64 {
+        let cos_max = self.cos_sun_radius();
+        if direction.normalize().dot(self.sun_dir.normalize()) < cos_max {
+            return 0.0;
+        }
+
+        1.0 / (2.0 * PI * (1.0 - cos_max))
+    }
+
+    /// Cosine of the sun's angular radius, kept short of a point so the disk has an area.
+    fn cos_sun_radius(&self) -> f64 {
+        self.sun_angular_radius.max(0.01).to_radians().cos()
+    }
+
+    /// Color of sunlight, which reddens as the sun sets and its light crosses more air.
+    fn sun_tint(&self) -> Color {
+        let elevation = self.sun_dir.normalize().y.clamp(0.0, 1.0);
+        Color::new(1.0, 0.5, 0.25).lerp(Color::new(1.0, 0.95, 0.9), elevation.powf(0.3))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn empty_maps_sample_without_light() {
+        let env = EnvironmentMap::from_texels(0, 0, vec![]);
+        let mut rng = StdRng::seed_from_u64(1);
+
+        let direction = env.sample(&mut rng);
+        assert!((direction.length() - 1.0).abs() < 1e-12);
+        assert_eq!(env.pdf(direction), 0.0);
+        assert_eq!(env.value(direction), Color::ZERO);
+    }
+}
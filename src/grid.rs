@@ -0,0 +1,299 @@
+use crate::{aabb::Aabb, HitRecord, Hittable, HittableList, Interval, Ray, Vec3};
+
+/// Cells per object that `UniformGrid::new` aims for.
+const CELLS_PER_OBJECT: f64 = 3.0;
+
+/// Upper limit for the number of cells along each axis of an automatically sized grid.
+const MAX_RESOLUTION: usize = 128;
+
+/// Objects whose bounding box has a diagonal this many times the median are too large to put in
+/// cells, since they'd spread the cells over the whole of them.
+const OVERSIZED_FACTOR: f64 = 64.0;
+
+/// A uniform grid of cells over the scene, each listing the objects whose bounding boxes overlap
+/// it. Rays walk through the cells they cross in order and only test the objects in them.
+///
+/// Grids suit scenes of many similarly sized objects spread evenly, and build faster than a
+/// `BvhNode`. Objects that are far larger than the rest, like a huge sphere as the ground, or
+/// unbounded, are left out of the cells and tested against every ray instead. Scenes of objects
+/// of all sizes are better off in a BVH.
+pub struct UniformGrid {
+    objects: Vec<Box<dyn Hittable>>,
+    /// Cells every object overlaps, as the first and last cell along each axis
+    ranges: Vec<([usize; 3], [usize; 3])>,
+    /// Objects without a finite or a reasonably small bounding box, which every ray is tested
+    /// against
+    unbounded: Vec<usize>,
+    /// Object indices in each cell, with x varying fastest
+    cells: Vec<Vec<u32>>,
+    resolution: [usize; 3],
+    /// Box around the objects in cells, which the cells divide up
+    grid_box: Aabb,
+    cell_size: Vec3,
+    bbox: Aabb,
+}
+
+impl UniformGrid {
+    /// Builds a grid with about `CELLS_PER_OBJECT` cells per object, as close to cubes as the
+    /// bounds of the objects allow.
+    pub fn new(list: HittableList) -> Self {
+        let boxes: Vec<Aabb> = list.iter().map(|object| object.bounding_box()).collect();
+        let in_cells = fits_in_cells(&boxes);
+        let celled = in_cells.iter().filter(|&&in_cells| in_cells).count();
+        let grid_box = union_where(&boxes, &in_cells);
+
+        // Flat scenes still get cells along their thin axis, sized after the widest one.
+        let diagonal = grid_box.diagonal().max(Vec3::ZERO);
+        let diagonal = diagonal.max(Vec3::splat(1e-3 * diagonal.max_element()));
+        let cells_per_unit = (CELLS_PER_OBJECT * celled as f64 / diagonal.element_product()).cbrt();
+        let resolution = (diagonal * cells_per_unit)
+            .round()
+            .to_array()
+            .map(|cells| (cells as usize).clamp(1, MAX_RESOLUTION));
+
+        Self::with_resolution(list, resolution)
+    }
+
+    /// Builds a grid with `resolution` cells along the x, y and z axes.
+    pub fn with_resolution(list: HittableList, resolution: [usize; 3]) -> Self {
+        let resolution = resolution.map(|cells| cells.max(1));
+        let objects: Vec<Box<dyn Hittable>> = list.into_iter().collect();
+
+        let boxes: Vec<Aabb> = objects.iter().map(|object| object.bounding_box()).collect();
+        let in_cells = fits_in_cells(&boxes);
+        let bbox = boxes
+            .iter()
+            .fold(Aabb::EMPTY, |bbox, object_box| bbox.union(object_box));
+        let grid_box = union_where(&boxes, &in_cells);
+        let cell_size = grid_box.diagonal().max(Vec3::ZERO)
+            / Vec3::new(
+                resolution[0] as f64,
+                resolution[1] as f64,
+                resolution[2] as f64,
+            );
+
+        let mut grid = Self {
+            objects: vec![],
+            ranges: vec![],
+            unbounded: vec![],
+            cells: vec![vec![]; resolution.iter().product()],
+            resolution,
+            grid_box,
+            cell_size,
+            bbox,
+        };
+
+        for (idx, object_box) in boxes.iter().enumerate() {
+            if !in_cells[idx] {
+                // The range is empty, it's in no cells.
+                grid.unbounded.push(idx);
+                grid.ranges.push(([1, 1, 1], [0, 0, 0]));
+                continue;
+            }
+
+            let lo = grid.cell_of(object_box.min());
+            let hi = grid.cell_of(object_box.max());
+            for z in lo[2]..=hi[2] {
+                for y in lo[1]..=hi[1] {
+                    for x in lo[0]..=hi[0] {
+                        let cell = grid.cell_index([x, y, z]);
+                        grid.cells[cell].push(idx as u32);
+                    }
+                }
+            }
+            grid.ranges.push((lo, hi));
+        }
+        grid.objects = objects;
+
+        grid
+    }
+
+    /// Returns the cell holding `p`, clamped to the grid.
+    fn cell_of(&self, p: Vec3) -> [usize; 3] {
+        let min = self.grid_box.min();
+        std::array::from_fn(|axis| {
+            if self.cell_size[axis] <= 0.0 {
+                return 0;
+            }
+            let cell = ((p[axis] - min[axis]) / self.cell_size[axis]).floor();
+            (cell.max(0.0) as usize).min(self.resolution[axis] - 1)
+        })
+    }
+
+    fn cell_index(&self, [x, y, z]: [usize; 3]) -> usize {
+        (z * self.resolution[1] + y) * self.resolution[0] + x
+    }
+
+    /// Returns the part of `ray_t` during which `r` is inside the grid, if any.
+    fn clip(&self, r: &Ray, mut ray_t: Interval) -> Option<Interval> {
+        for axis in 0..3 {
+            let ax = self.grid_box.axis_interval(axis);
+            if r.direction()[axis] == 0.0 {
+                if !ax.contains(r.origin[axis]) {
+                    return None;
+                }
+                continue;
+            }
+
+            let adinv = 1.0 / r.direction()[axis];
+            let t0 = (ax.min - r.origin[axis]) * adinv;
+            let t1 = (ax.max - r.origin[axis]) * adinv;
+            ray_t.min = ray_t.min.max(t0.min(t1));
+            ray_t.max = ray_t.max.min(t0.max(t1));
+        }
+
+        (ray_t.min <= ray_t.max).then_some(ray_t)
+    }
+}
+
+impl Hittable for UniformGrid {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let mut closest = None;
+        let mut closest_so_far = ray_t.max;
+        let mut test = |idx: usize, closest_so_far: &mut f64| {
+            if let Some(rec) = self.objects[idx].hit(r, Interval::new(ray_t.min, *closest_so_far)) {
+                *closest_so_far = rec.t;
+                closest = Some(rec);
+            }
+        };
+
+        for &idx in &self.unbounded {
+            test(idx, &mut closest_so_far);
+        }
+
+        let Some(inside) = self.clip(r, Interval::new(ray_t.min, closest_so_far)) else {
+            return closest;
+        };
+
+        // 3D-DDA: step into whichever neighboring cell the ray reaches first, tracking the ray
+        // parameter at which it crosses the next cell boundary along each axis.
+        let min = self.grid_box.min();
+        let mut cell = self.cell_of(r.at(inside.min));
+        let mut step = [0_isize; 3];
+        let mut t_next = [f64::INFINITY; 3];
+        let mut t_delta = [f64::INFINITY; 3];
+        for axis in 0..3 {
+            let d = r.direction()[axis];
+            if d == 0.0 || self.cell_size[axis] <= 0.0 {
+                continue;
+            }
+
+            let next = if d > 0.0 { cell[axis] + 1 } else { cell[axis] };
+            let boundary = min[axis] + next as f64 * self.cell_size[axis];
+            step[axis] = if d > 0.0 { 1 } else { -1 };
+            t_next[axis] = (boundary - r.origin[axis]) / d;
+            t_delta[axis] = self.cell_size[axis] / d.abs();
+        }
+
+        let mut previous: Option<[usize; 3]> = None;
+        loop {
+            for &idx in &self.cells[self.cell_index(cell)] {
+                // An object spanning several cells is tested in the first of them the ray
+                // crosses. The cells of its range the ray crosses come one after the other,
+                // so it was tested already if the ray came from one of them.
+                let (lo, hi) = self.ranges[idx as usize];
+                let tested = previous.is_some_and(|prev| {
+                    (0..3).all(|axis| (lo[axis]..=hi[axis]).contains(&prev[axis]))
+                });
+                if !tested {
+                    test(idx as usize, &mut closest_so_far);
+                }
+            }
+
+            // Hits are final once the ray leaves the cell past them, since the rest of the
+            // cells are farther away.
+            let axis = if t_next[0] < t_next[1] {
+                if t_next[0] < t_next[2] {
+                    0
+                } else {
+                    2
+                }
+            } else if t_next[1] < t_next[2] {
+                1
+            } else {
+                2
+            };
+            let cell_exit = t_next[axis];
+            if closest_so_far <= cell_exit || cell_exit > inside.max {
+                break;
+            }
+
+            let Some(next) = cell[axis].checked_add_signed(step[axis]) else {
+                break;
+            };
+            if next >= self.resolution[axis] {
+                break;
+            }
+            previous = Some(cell);
+            cell[axis] = next;
+            t_next[axis] += t_delta[axis];
+        }
+
+        closest
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn bvh_node_count(&self) -> u64 {
+        self.objects
+            .iter()
+            .map(|object| object.bvh_node_count())
+            .sum()
+    }
+}
+
+/// Returns for every box whether its object goes in cells, which is when it's finite and not
+/// an outlier in size.
+fn fits_in_cells(boxes: &[Aabb]) -> Vec<bool> {
+    let is_finite =
+        |bbox: &Aabb| bbox.is_valid() && bbox.min().is_finite() && bbox.max().is_finite();
+
+    let mut sizes: Vec<f64> = boxes
+        .iter()
+        .filter(|bbox| is_finite(bbox))
+        .map(|bbox| bbox.diagonal().length())
+        .collect();
+    sizes.sort_unstable_by(f64::total_cmp);
+    let max_size = sizes
+        .get(sizes.len() / 2)
+        .map_or(0.0, |median| OVERSIZED_FACTOR * median);
+
+    boxes
+        .iter()
+        .map(|bbox| is_finite(bbox) && bbox.diagonal().length() <= max_size)
+        .collect()
+}
+
+/// Returns the union of the boxes selected by `selected`.
+fn union_where(boxes: &[Aabb], selected: &[bool]) -> Aabb {
+    boxes
+        .iter()
+        .zip(selected)
+        .filter(|(_, &selected)| selected)
+        .fold(Aabb::EMPTY, |bbox, (object_box, _)| bbox.union(object_box))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::tests::{assert_same_hits, scene};
+
+    #[test]
+    fn grid_hits_like_list() {
+        let grid = UniformGrid::new(scene(300));
+        assert_same_hits(&grid, &scene(300));
+    }
+
+    #[test]
+    fn fine_grid_hits_like_list() {
+        // Cells much smaller than the objects, so most objects span several of them and rays
+        // cross many cell boundaries.
+        let grid = UniformGrid::with_resolution(scene(300), [40, 40, 40]);
+        let spanning = grid.ranges.iter().filter(|(lo, hi)| lo != hi).count();
+        assert!(spanning > 100, "only {spanning} objects span several cells");
+
+        assert_same_hits(&grid, &scene(300));
+    }
+}
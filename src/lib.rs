@@ -0,0 +1,624 @@
+use std::{f64::consts::PI, fmt, ops::Range, sync::Arc};
+
+use aabb::Aabb;
+use glam::{dvec3 as vec3, DVec3 as Vec3};
+use material::Material;
+use pdf::Onb;
+use rand::{Rng, RngCore};
+
+pub mod aabb;
+pub mod animation;
+pub mod bvh;
+pub mod camera;
+pub mod color;
+pub mod csg;
+pub mod environment;
+pub mod grid;
+pub mod material;
+pub mod medium;
+pub mod obj;
+pub mod object_id;
+pub mod pdf;
+pub mod perlin;
+pub mod sampling;
+pub mod scene;
+pub mod shadow_map;
+pub mod shapes;
+pub mod stats;
+pub mod texture;
+pub mod transform;
+pub mod visibility;
+
+pub type Point3 = Vec3;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Ray {
+    pub origin: Point3,
+    /// Private so that `length_squared` can't go stale, see `with_direction`.
+    direction: Vec3,
+    pub kind: RayKind,
+    /// Moment within the shutter interval [0,1) the ray was sent at
+    pub time: f64,
+    /// Wavelength in nanometers of the light the ray carries, for dispersive materials. `None`
+    /// for rays carrying all colors.
+    pub wavelength: Option<f64>,
+    /// `direction.length_squared()`, computed once instead of by every object the ray is tested
+    /// against. Rotations and translations keep it valid, so transformed copies can share it.
+    length_squared: f64,
+}
+
+/// What a ray is being traced for, so objects can choose which rays they're visible to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RayKind {
+    /// Rays leaving the camera
+    Primary,
+    /// Rays probing whether a point is occluded, e.g. for shadow maps
+    Shadow,
+    /// Reflected, refracted and scattered rays
+    #[default]
+    Secondary,
+}
+
+impl Ray {
+    /// Creates a secondary ray.
+    pub fn new(orig: Point3, dir: Vec3) -> Self {
+        Self {
+            origin: orig,
+            direction: dir,
+            kind: RayKind::Secondary,
+            time: 0.0,
+            wavelength: None,
+            length_squared: dir.length_squared(),
+        }
+    }
+
+    pub fn direction(&self) -> Vec3 {
+        self.direction
+    }
+
+    /// The ray sent from the same origin in `direction` instead.
+    pub fn with_direction(self, direction: Vec3) -> Self {
+        Self {
+            direction,
+            length_squared: direction.length_squared(),
+            ..self
+        }
+    }
+
+    pub fn with_kind(self, kind: RayKind) -> Self {
+        Self { kind, ..self }
+    }
+
+    pub fn with_time(self, time: f64) -> Self {
+        Self { time, ..self }
+    }
+
+    pub fn with_wavelength(self, wavelength: Option<f64>) -> Self {
+        Self { wavelength, ..self }
+    }
+
+    pub fn at(&self, t: f64) -> Point3 {
+        self.origin + t * self.direction
+    }
+
+    /// The squared length of `direction`.
+    pub fn length_squared(&self) -> f64 {
+        self.length_squared
+    }
+}
+
+#[derive(Clone)]
+pub struct HitRecord {
+    pub p: Point3,
+    pub normal: Vec3,
+    pub mat: Arc<dyn Material>,
+    pub t: f64,
+    /// Surface texture coordinates of the hit point
+    pub u: f64,
+    pub v: f64,
+    /// Unit direction along the surface in which `u` increases, for orienting normal maps. Zero
+    /// for shapes that don't compute one.
+    pub tangent: Vec3,
+    pub front_face: bool,
+    /// ID of the innermost `Identified` object around the surface, if any
+    pub object_id: Option<u16>,
+    /// How far the hit point moves over the shutter interval, for motion vectors. Zero for
+    /// objects that stand still.
+    pub motion: Vec3,
+}
+
+impl HitRecord {
+    pub fn new(p: Point3, t: f64, mat: Arc<dyn Material>, r: &Ray, outward_normal: Vec3) -> Self {
+        // Sets the hit record normal vector.
+        // NOTE: the parameter `outward_normal` is assumed to have unit length.
+        let front_face = r.direction().dot(outward_normal) < 0.0;
+        let normal = if front_face {
+            outward_normal
+        } else {
+            -outward_normal
+        };
+
+        Self {
+            p,
+            normal,
+            mat,
+            t,
+            u: 0.0,
+            v: 0.0,
+            tangent: Vec3::ZERO,
+            front_face,
+            object_id: None,
+            motion: Vec3::ZERO,
+        }
+    }
+}
+
+/// Anything a ray can intersect.
+///
+/// Scenes are never mutated while rendering, so a world can be shared between threads by
+/// reference. Implementors must therefore be `Send + Sync`.
+pub trait Hittable: Send + Sync {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord>;
+
+    fn bounding_box(&self) -> Aabb;
+
+    /// Hits like `hit`, and adds the number of BVH nodes the ray was tested against to
+    /// `nodes_visited`, for `DebugMode::BvhNodes`. Only BVHs and the lists holding them count
+    /// nodes, nodes inside other objects aren't counted.
+    fn hit_counting(
+        &self,
+        r: &Ray,
+        ray_t: Interval,
+        _nodes_visited: &mut u64,
+    ) -> Option<HitRecord> {
+        self.hit(r, ray_t)
+    }
+
+    /// Returns the number of BVH nodes in the object, for `RenderStats::bvh_nodes`. Objects that
+    /// hold other objects add up the nodes in them.
+    fn bvh_node_count(&self) -> u64 {
+        0
+    }
+
+    /// Returns the density, over directions from `origin`, with which `random` picks
+    /// `direction`. Only objects used as lights for importance sampling need to implement this.
+    ///
+    /// Like the shadow rays towards the object, a ray from `origin` along `direction` only
+    /// counts as reaching it past `shadow_bias`, see `CameraParams::shadow_bias`.
+    fn pdf_value(&self, _origin: Point3, _direction: Vec3, _shadow_bias: f64) -> f64 {
+        0.0
+    }
+
+    /// Returns a random direction from `origin` towards the object.
+    fn random(&self, _origin: Point3, _rng: &mut dyn RngCore) -> Vec3 {
+        vec3(1.0, 0.0, 0.0)
+    }
+
+    /// Picks a random point on the surface at `time` in the shutter interval, for tracing light
+    /// from it, and returns it as hit from the side its normal faces, along with the density of
+    /// picking it per unit area.
+    /// Lights that return `None`, the default, aren't traced by the caustics pass, see
+    /// `CameraParams::light_paths_per_pixel`.
+    fn sample_surface(&self, _time: f64, _rng: &mut dyn RngCore) -> Option<(HitRecord, f64)> {
+        None
+    }
+}
+
+pub struct Sphere {
+    /// Center at time 0
+    pub center: Point3,
+    /// Center at time 1, for spheres that move while the shutter is open
+    pub center_end: Option<Point3>,
+    pub radius: f64,
+    pub mat: Arc<dyn Material>,
+}
+
+impl Sphere {
+    pub fn new(center: Point3, radius: f64, mat: Arc<dyn Material>) -> Self {
+        Self {
+            center,
+            center_end: None,
+            radius,
+            mat,
+        }
+    }
+
+    /// Creates a sphere moving linearly from `center` to `center_end` over the shutter interval.
+    pub fn moving(center: Point3, center_end: Point3, radius: f64, mat: Arc<dyn Material>) -> Self {
+        Self {
+            center_end: Some(center_end),
+            ..Self::new(center, radius, mat)
+        }
+    }
+
+    pub fn center_at(&self, time: f64) -> Point3 {
+        match self.center_end {
+            Some(center_end) => self.center.lerp(center_end, time),
+            None => self.center,
+        }
+    }
+
+    /// Returns the texture coordinates of a point `p` on the unit sphere centered at the origin.
+    /// `u` goes around the Y axis from X=-1, and `v` goes from Y=-1 to Y=+1.
+    fn get_sphere_uv(p: Point3) -> (f64, f64) {
+        let theta = (-p.y).acos();
+        let phi = (-p.z).atan2(p.x) + PI;
+
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    /// Returns the direction in which `u` of `get_sphere_uv` increases at the point `p` on the
+    /// unit sphere, zero at the poles.
+    fn get_sphere_tangent(p: Point3) -> Vec3 {
+        vec3(p.z, 0.0, -p.x).normalize_or_zero()
+    }
+}
+
+impl Hittable for Sphere {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let center = self.center_at(r.time);
+        let oc = center - r.origin;
+
+        let h = r.direction().dot(oc);
+        let c = oc.length_squared() - self.radius * self.radius;
+
+        // Both roots are behind a ray that starts outside the sphere and points away from it.
+        if c > 0.0 && h < 0.0 && ray_t.min >= 0.0 {
+            return None;
+        }
+
+        let a = r.length_squared();
+        let discriminant = h * h - a * c;
+
+        if discriminant < 0.0 {
+            return None;
+        }
+
+        let sqrtd = discriminant.sqrt();
+
+        // Find the nearest root that lies in the acceptable range.
+        let mut root = (h - sqrtd) / a;
+        if !ray_t.surrounds(root) {
+            root = (h + sqrtd) / a;
+            if !ray_t.surrounds(root) {
+                return None;
+            }
+        }
+
+        let p = r.at(root);
+
+        let outward_normal = (p - center) / self.radius;
+        let mut rec = HitRecord::new(p, root, self.mat.clone(), r, outward_normal);
+        (rec.u, rec.v) = Self::get_sphere_uv(outward_normal);
+        rec.tangent = Self::get_sphere_tangent(outward_normal);
+        if let Some(center_end) = self.center_end {
+            rec.motion = center_end - self.center;
+        }
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let rvec = Vec3::splat(self.radius);
+        let bbox = Aabb::from_points(self.center - rvec, self.center + rvec);
+
+        match self.center_end {
+            Some(center_end) => {
+                bbox.union(&Aabb::from_points(center_end - rvec, center_end + rvec))
+            }
+            None => bbox,
+        }
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3, shadow_bias: f64) -> f64 {
+        // This is only correct for stationary spheres.
+        if self
+            .hit(
+                &Ray::new(origin, direction),
+                Interval::new(shadow_bias, f64::INFINITY),
+            )
+            .is_none()
+        {
+            return 0.0;
+        }
+
+        let distance_squared = (self.center - origin).length_squared();
+        if distance_squared <= self.radius * self.radius {
+            // From inside, the sphere covers every direction.
+            return 1.0 / (4.0 * PI);
+        }
+
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+
+        1.0 / solid_angle
+    }
+
+    fn random(&self, origin: Point3, rng: &mut dyn RngCore) -> Vec3 {
+        let direction = self.center - origin;
+        let distance_squared = direction.length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return random_vec3_unit(rng);
+        }
+
+        // Sample the cone of directions the sphere covers as seen from `origin`.
+        let r1: f64 = rng.gen();
+        let r2: f64 = rng.gen();
+        let z = 1.0 + r2 * ((1.0 - self.radius * self.radius / distance_squared).sqrt() - 1.0);
+        let phi = 2.0 * PI * r1;
+        let x = phi.cos() * (1.0 - z * z).sqrt();
+        let y = phi.sin() * (1.0 - z * z).sqrt();
+
+        Onb::new(direction).transform(vec3(x, y, z))
+    }
+
+    fn sample_surface(&self, time: f64, rng: &mut dyn RngCore) -> Option<(HitRecord, f64)> {
+        let outward_normal = random_vec3_unit(rng);
+        let p = self.center_at(time) + self.radius * outward_normal;
+        let r = Ray::new(p + outward_normal, -outward_normal).with_time(time);
+        let mut rec = HitRecord::new(p, 0.0, self.mat.clone(), &r, outward_normal);
+        (rec.u, rec.v) = Self::get_sphere_uv(outward_normal);
+        rec.tangent = Self::get_sphere_tangent(outward_normal);
+
+        Some((rec, 1.0 / (4.0 * PI * self.radius * self.radius)))
+    }
+}
+
+#[derive(Default)]
+pub struct HittableList {
+    objects: Vec<Box<dyn Hittable>>,
+}
+
+impl HittableList {
+    pub fn new() -> Self {
+        Self { objects: vec![] }
+    }
+
+    /// Adds an object to the end of the list and returns its index.
+    pub fn add(&mut self, object: Box<dyn Hittable>) -> usize {
+        self.objects.push(object);
+        self.objects.len() - 1
+    }
+
+    /// Removes and returns the object at `index`. Like `Vec::remove`, this shifts the indices of
+    /// all objects after it down by one.
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn remove(&mut self, index: usize) -> Box<dyn Hittable> {
+        self.objects.remove(index)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&dyn Hittable> {
+        self.objects.get(index).map(|obj| obj.as_ref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Hittable> {
+        self.objects.iter().map(|obj| obj.as_ref())
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Returns the box around the objects with a finite bounding box, leaving out unbounded
+    /// ones like an `InfinitePlane`, whose box is all of space.
+    pub fn finite_bounding_box(&self) -> Aabb {
+        self.objects
+            .iter()
+            .map(|obj| obj.bounding_box())
+            .filter(|bbox| bbox.is_valid())
+            .fold(Aabb::EMPTY, |bbox, obj_bbox| bbox.union(&obj_bbox))
+    }
+}
+
+impl IntoIterator for HittableList {
+    type Item = Box<dyn Hittable>;
+    type IntoIter = std::vec::IntoIter<Box<dyn Hittable>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.objects.into_iter()
+    }
+}
+
+impl HittableList {
+    /// Returns the closest of the hits `hit` finds on each object within `ray_t`.
+    fn closest_hit(
+        &self,
+        ray_t: Interval,
+        mut hit: impl FnMut(&dyn Hittable, Interval) -> Option<HitRecord>,
+    ) -> Option<HitRecord> {
+        let mut hit_anything = None;
+        let mut closest_so_far = ray_t.max;
+
+        for obj in &self.objects {
+            if let Some(rec) = hit(obj.as_ref(), Interval::new(ray_t.min, closest_so_far)) {
+                closest_so_far = rec.t;
+                hit_anything = Some(rec);
+            }
+        }
+
+        hit_anything
+    }
+}
+
+impl Hittable for HittableList {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        self.closest_hit(ray_t, |obj, ray_t| obj.hit(r, ray_t))
+    }
+
+    fn hit_counting(&self, r: &Ray, ray_t: Interval, nodes_visited: &mut u64) -> Option<HitRecord> {
+        self.closest_hit(ray_t, |obj, ray_t| {
+            obj.hit_counting(r, ray_t, nodes_visited)
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.objects
+            .iter()
+            .fold(Aabb::EMPTY, |bbox, obj| bbox.union(&obj.bounding_box()))
+    }
+
+    fn bvh_node_count(&self) -> u64 {
+        self.objects.iter().map(|obj| obj.bvh_node_count()).sum()
+    }
+
+    /// The average density of all objects, since `random` picks one of them at random.
+    fn pdf_value(&self, origin: Point3, direction: Vec3, shadow_bias: f64) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+
+        let sum: f64 = self
+            .objects
+            .iter()
+            .map(|obj| obj.pdf_value(origin, direction, shadow_bias))
+            .sum();
+
+        sum / self.objects.len() as f64
+    }
+
+    fn random(&self, origin: Point3, rng: &mut dyn RngCore) -> Vec3 {
+        if self.objects.is_empty() {
+            return vec3(1.0, 0.0, 0.0);
+        }
+
+        let idx = rng.gen_range(0..self.objects.len());
+        self.objects[idx].random(origin, rng)
+    }
+
+    /// Picks one of the objects at random like `random`, so the density is that of the object
+    /// over their number.
+    fn sample_surface(&self, time: f64, rng: &mut dyn RngCore) -> Option<(HitRecord, f64)> {
+        if self.objects.is_empty() {
+            return None;
+        }
+
+        let idx = rng.gen_range(0..self.objects.len());
+        let (rec, pdf) = self.objects[idx].sample_surface(time, rng)?;
+        Some((rec, pdf / self.objects.len() as f64))
+    }
+}
+
+// A whole scene, including its shared materials, must be able to cross thread boundaries.
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<HittableList>();
+    assert_send_sync::<HitRecord>();
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Interval {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Interval {
+    /// The interval containing nothing, which is the identity for `new_enclosing`.
+    pub const EMPTY: Self = Self {
+        min: f64::INFINITY,
+        max: f64::NEG_INFINITY,
+    };
+
+    /// The interval containing every number.
+    pub const UNIVERSE: Self = Self {
+        min: f64::NEG_INFINITY,
+        max: f64::INFINITY,
+    };
+
+    pub fn new(min: f64, max: f64) -> Self {
+        Self { min, max }
+    }
+
+    /// Returns the smallest interval spanning both `a` and `b`.
+    pub fn new_enclosing(a: Interval, b: Interval) -> Self {
+        Self {
+            min: a.min.min(b.min),
+            max: a.max.max(b.max),
+        }
+    }
+
+    pub fn size(&self) -> f64 {
+        self.max - self.min
+    }
+
+    /// Returns false for inverted intervals (`min > max`) or non-finite bounds.
+    pub fn is_valid(&self) -> bool {
+        self.min <= self.max && self.min.is_finite() && self.max.is_finite()
+    }
+
+    /// Like `surrounds`, but also true for `x` on the bounds.
+    pub fn contains(&self, x: f64) -> bool {
+        self.min <= x && x <= self.max
+    }
+
+    pub fn surrounds(&self, x: f64) -> bool {
+        self.min < x && x < self.max
+    }
+
+    pub fn clamp(&self, x: f64) -> f64 {
+        if x < self.min {
+            return self.min;
+        }
+        if x > self.max {
+            return self.max;
+        }
+
+        x
+    }
+
+    /// Returns the interval grown by `delta` in total, half on each side.
+    pub fn expand(&self, delta: f64) -> Self {
+        let padding = delta / 2.0;
+
+        Self::new(self.min - padding, self.max + padding)
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}, {}]", self.min, self.max)
+    }
+}
+
+pub fn random_vec3(rng: &mut dyn RngCore) -> Vec3 {
+    vec3(rng.gen(), rng.gen(), rng.gen())
+}
+
+pub fn random_vec3_range(rng: &mut dyn RngCore, r: Range<f64>) -> Vec3 {
+    vec3(
+        rng.gen_range(r.clone()),
+        rng.gen_range(r.clone()),
+        rng.gen_range(r),
+    )
+}
+
+pub fn random_vec3_unit(rng: &mut dyn RngCore) -> Vec3 {
+    loop {
+        let p = random_vec3_range(rng, (-1.0)..1.0);
+        if p.length_squared() < 1.0 {
+            return p.normalize();
+        }
+    }
+}
+
+pub fn random_vec3_on_hempishere(rng: &mut dyn RngCore, normal: &Vec3) -> Vec3 {
+    let on_unit_sphere = random_vec3_unit(rng);
+    if normal.dot(on_unit_sphere) > 0.0 {
+        // In the same hemisphere as the normal
+        on_unit_sphere
+    } else {
+        -on_unit_sphere
+    }
+}
+pub fn random_vec3_on_unit_disc(rng: &mut dyn RngCore) -> Vec3 {
+    loop {
+        let p = vec3(rng.gen_range((-1.0)..1.0), rng.gen_range((-1.0)..1.0), 0.0);
+        if p.length_squared() < 1.0 {
+            return p;
+        }
+    }
+}
@@ -1,296 +1,394 @@
-use std::{ops::Range, rc::Rc};
-
-use camera::{Camera, CameraParams};
-use color::Color;
-use glam::{dvec3 as vec3, DVec3 as Vec3};
-use material::{Dielectric, Lambertian, Material, Metal};
-use rand::Rng;
-
-mod camera;
-mod color;
-mod material;
+use std::{f64::consts::PI, sync::Arc};
+
+use glam::dvec3 as vec3;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use raytracing_in_one_weekend::{
+    animation::{render_animation, render_animation_temporal, TemporalBlend},
+    bvh::BvhNode,
+    camera::{
+        AoParams, Background, Camera, CameraParams, DebugMode, PixelSampling, RefinementStrategy,
+        Shutter, TileOrder,
+    },
+    color::{Color, GammaMode, ToneMap},
+    environment::{EnvironmentMap, ProceduralSky},
+    grid::UniformGrid,
+    material::{Dielectric, Lambertian, Material, Metal},
+    obj::load_obj,
+    object_id::Identified,
+    random_vec3,
+    scene::{load_scene, load_scene_cameras},
+    shapes::BoundingProxy,
+    texture::CheckerTexture,
+    Hittable, HittableList, Point3, Sphere,
+};
 
 fn main() {
-    let mut world = HittableList::new();
-
-    let mat_ground = Rc::new(Lambertian {
-        albedo: Color::new(0.5, 0.5, 0.5),
-    });
-    world.add(Box::new(Sphere {
-        center: vec3(0.0, -1000.0, -1.0),
-        radius: 1000.0,
-        mat: mat_ground,
-    }));
-
-    let mut rng = rand::thread_rng();
-    let mut random = || rng.gen::<f64>();
-
-    for a in -11..11 {
-        for b in -11..11 {
-            let center = Point3::new(a as f64 + 0.9 * random(), 0.2, b as f64 + 0.9 * random());
-
-            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                let num = random();
-                let mat: Rc<dyn Material> = if num < 0.8 {
-                    let albedo = random_vec3() * random_vec3();
-
-                    Rc::new(Lambertian { albedo })
-                } else if num < 0.95 {
-                    let albedo = Color::new(0.5, 1.0, random());
-                    let fuzz = rand::thread_rng().gen_range((0.0)..0.5);
-                    Rc::new(Metal { albedo, fuzz })
-                } else {
-                    Rc::new(Dielectric {
-                        refraction_index: 1.5,
-                    })
+    let args: Vec<String> = std::env::args().collect();
+
+    // `--frames <n>` renders n frames orbiting the camera around the scene, as PNGs in the
+    // `--output` directory. `--temporal <alpha>` blends every frame into the next, weighing the
+    // new render by alpha.
+    if let Some(frames) = arg_value(&args, "--frames").and_then(|n| n.parse::<usize>().ok()) {
+        let dir = arg_value(&args, "--output").unwrap_or(".");
+        let scene = |frame| {
+            let (world, mut params) = build_scene(&args);
+            let angle = 2.0 * PI * frame as f64 / frames as f64;
+            let (sin, cos) = angle.sin_cos();
+            let offset = params.lookfrom - params.lookat;
+            params.lookfrom = params.lookat
+                + vec3(
+                    cos * offset.x + sin * offset.z,
+                    offset.y,
+                    -sin * offset.x + cos * offset.z,
+                );
+            (world, params)
+        };
+        let result = match arg_value(&args, "--temporal").and_then(|n| n.parse().ok()) {
+            Some(alpha) => {
+                let blend = TemporalBlend {
+                    alpha,
+                    ..TemporalBlend::default()
                 };
-
-                world.add(Box::new(Sphere {
-                    center,
-                    radius: 0.2,
-                    mat,
-                }));
+                render_animation_temporal(frames, dir, blend, scene)
             }
+            None => render_animation(frames, dir, scene),
+        };
+        if let Err(err) = result {
+            eprintln!("Failed to write frames to {dir}: {err}");
+            std::process::exit(1);
         }
+        return;
     }
 
-    let mat1 = Rc::new(Dielectric {
-        refraction_index: 1.5,
-    });
-    world.add(Box::new(Sphere {
-        center: vec3(0.0, 1.0, 0.0),
-        radius: 1.0,
-        mat: mat1,
-    }));
-
-    let mat2 = Rc::new(Lambertian {
-        albedo: Color::new(0.4, 0.2, 0.1),
-    });
-    world.add(Box::new(Sphere {
-        center: vec3(-4.0, 1.0, 0.0),
-        radius: 1.0,
-        mat: mat2,
-    }));
-
-    let mat3 = Rc::new(Metal {
-        albedo: Color::new(0.7, 0.6, 0.5),
-        fuzz: 0.0,
-    });
-    world.add(Box::new(Sphere {
-        center: vec3(4.0, 1.0, 0.0),
-        radius: 1.0,
-        mat: mat3,
-    }));
-
-    let mut cam = Camera::new(CameraParams {
-        aspect_ratio: 16.0 / 9.0,
-        image_width: 600,
-        samples_per_pixel: 100,
-        max_depth: 25,
-        vfov: 20.0,
-        lookfrom: Point3::new(13.0, 2.0, 3.0),
-        lookat: Point3::ZERO,
-        vup: vec3(0., 1., 0.),
-        defocus_angle: 0.6,
-        focus_dist: 10.0,
-    });
-
-    cam.render(world);
-}
-
-type Point3 = Vec3;
+    // `--cameras <dir>` renders the scene file through each of its named cameras, as PNGs in
+    // that directory.
+    if let Some(dir) = arg_value(&args, "--cameras") {
+        let Some(path) = args.get(1).filter(|arg| !arg.starts_with("--")) else {
+            eprintln!("--cameras needs a scene file");
+            std::process::exit(1);
+        };
+        let result = load_scene_cameras(path)
+            .map_err(image::ImageError::IoError)
+            .and_then(|(world, cameras)| cameras.render_all_cameras(world, dir));
+        if let Err(err) = result {
+            eprintln!("Failed to render the cameras of {path} to {dir}: {err}");
+            std::process::exit(1);
+        }
+        return;
+    }
 
-#[derive(Default)]
-struct Ray {
-    origin: Point3,
-    direction: Vec3,
-}
+    let (world, params) = build_scene(&args);
+    let mut cam = Camera::new(params);
 
-impl Ray {
-    pub fn new(orig: Point3, dir: Vec3) -> Self {
-        Self {
-            origin: orig,
-            direction: dir,
+    // `--grid` puts the scene in a uniform grid instead of a BVH.
+    let accelerated: Box<dyn Hittable> = if args.iter().any(|arg| arg == "--grid") {
+        Box::new(UniformGrid::new(world))
+    } else {
+        Box::new(BvhNode::new(world))
+    };
+    let mut world = HittableList::new();
+    world.add(accelerated);
+    // `--depth <path>` also writes the distance to the first hit of every pixel as a grayscale
+    // image.
+    if let Some(path) = arg_value(&args, "--depth") {
+        if let Err(err) = cam.render_depth_to_file(&world, path) {
+            eprintln!("Failed to write {path}: {err}");
         }
     }
 
-    fn at(&self, t: f64) -> Point3 {
-        self.origin + t * self.direction
+    // `--albedo <path>` and `--normals <path>` write the auxiliary images denoisers take.
+    if let Some(path) = arg_value(&args, "--albedo") {
+        if let Err(err) = cam.render_albedo_to_file(&world, path) {
+            eprintln!("Failed to write {path}: {err}");
+        }
     }
-}
-
-struct HitRecord {
-    p: Point3,
-    normal: Vec3,
-    mat: Rc<dyn Material>,
-    t: f64,
-    front_face: bool,
-}
-
-impl HitRecord {
-    fn new(p: Point3, t: f64, mat: Rc<dyn Material>, r: &Ray, outward_normal: Vec3) -> Self {
-        // Sets the hit record normal vector.
-        // NOTE: the parameter `outward_normal` is assumed to have unit length.
-        let front_face = r.direction.dot(outward_normal) < 0.0;
-        let normal = if front_face {
-            outward_normal
-        } else {
-            -outward_normal
-        };
-
-        Self {
-            p,
-            normal,
-            mat,
-            t,
-            front_face,
+    if let Some(path) = arg_value(&args, "--normals") {
+        if let Err(err) = cam.render_normals_to_file(&world, path) {
+            eprintln!("Failed to write {path}: {err}");
         }
     }
-}
-
-trait Hittable {
-    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord>;
-}
-
-struct Sphere {
-    center: Point3,
-    radius: f64,
-    mat: Rc<dyn Material>,
-}
-
-impl Hittable for Sphere {
-    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
-        let oc = self.center - r.origin;
-
-        let a = r.direction.length_squared();
-        let h = r.direction.dot(oc);
-        let c = oc.length_squared() - self.radius * self.radius;
-        let discriminant = h * h - a * c;
-
-        if discriminant < 0.0 {
-            return None;
+    // `--ids <path>` writes the IDs of the objects in every pixel as a 16-bit PNG.
+    if let Some(path) = arg_value(&args, "--ids") {
+        if let Err(err) = cam.render_ids_to_file(&world, path) {
+            eprintln!("Failed to write {path}: {err}");
         }
+    }
 
-        let sqrtd = discriminant.sqrt();
-
-        // Find the nearest root that lies in the acceptable range.
-        let mut root = (h - sqrtd) / a;
-        if !ray_t.surrounds(root) {
-            root = (h + sqrtd) / a;
-            if !ray_t.surrounds(root) {
-                return None;
+    // `--output <path>` writes an image file instead of PPM to stdout. `--linear <path>` also
+    // writes a 16-bit PNG of the same render with linear colors.
+    let stats = match arg_value(&args, "--output") {
+        Some(path) => {
+            let (image, stats) = cam.render_with_stats(world);
+            if let Some(linear_path) = arg_value(&args, "--linear") {
+                if let Err(err) = cam.save_linear_png(&image, linear_path) {
+                    eprintln!("Failed to write {linear_path}: {err}");
+                }
             }
+            if let Err(err) = cam.save_image(&image, path) {
+                eprintln!("Failed to write {path}: {err}");
+                std::process::exit(1);
+            }
+            stats
         }
+        // `--binary` writes binary PPM (P6) instead of plain text PPM.
+        None => {
+            let result = if args.iter().any(|arg| arg == "--binary") {
+                cam.render_binary(world)
+            } else {
+                cam.render(world)
+            };
+            match result {
+                Ok(stats) => stats,
+                Err(err) => {
+                    eprintln!("Failed to write image: {err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+    };
 
-        let p = r.at(root);
-
-        let rec = HitRecord::new(
-            p,
-            root,
-            self.mat.clone(),
-            r,
-            (p - self.center) / self.radius,
-        );
-
-        Some(rec)
+    // `--stats <path>` writes a JSON summary of the render next to the image.
+    if let Some(path) = arg_value(&args, "--stats") {
+        if let Err(err) = stats.write_json(path) {
+            eprintln!("Failed to write stats to {path}: {err}");
+        }
     }
 }
 
-struct HittableList {
-    objects: Vec<Box<dyn Hittable>>,
-}
-
-impl HittableList {
-    fn new() -> Self {
-        Self { objects: vec![] }
+/// Builds the world and camera from the command line arguments.
+fn build_scene(args: &[String]) -> (HittableList, CameraParams) {
+    // `--seed <n>` picks a different, but still reproducible, scene and render.
+    let seed = arg_value(args, "--seed")
+        .and_then(|seed| seed.parse().ok())
+        .unwrap_or(0);
+    let motion_blur = args.iter().any(|arg| arg == "--motion-blur");
+
+    // A scene file given as the first argument replaces the random spheres scene.
+    let (mut world, mut params) = match args.get(1).filter(|arg| !arg.starts_with("--")) {
+        Some(path) => match load_scene(path) {
+            Ok(scene) => scene,
+            Err(err) => {
+                eprintln!("Failed to load {path}: {err}");
+                std::process::exit(1);
+            }
+        },
+        None => random_spheres(seed, motion_blur),
+    };
+
+    // `--obj <path>` adds a mesh to the scene, and `--proxy` stands in its bounding box for it.
+    if let Some(path) = arg_value(args, "--obj") {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        match load_obj(path, mat) {
+            Ok(mesh) if args.iter().any(|arg| arg == "--proxy") => {
+                world.add(Box::new(BoundingProxy::new(Box::new(mesh))));
+            }
+            Ok(mesh) => {
+                for triangle in mesh {
+                    world.add(triangle);
+                }
+            }
+            Err(err) => eprintln!("Failed to load {path}: {err}"),
+        }
     }
 
-    fn add(&mut self, object: Box<dyn Hittable>) {
-        self.objects.push(object)
+    params.seed = seed;
+    // `--env-map <path>` lights the scene with an equirectangular (HDR) image.
+    if let Some(path) = arg_value(args, "--env-map") {
+        match EnvironmentMap::load(path) {
+            Ok(env) => params.background = Background::Environment(Arc::new(env)),
+            Err(err) => eprintln!("Failed to load {path}: {err}"),
+        }
     }
-}
-
-impl Hittable for HittableList {
-    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
-        let mut hit_anything = None;
-        let mut closest_so_far = ray_t.max;
-
-        for obj in &self.objects {
-            if let Some(rec) = obj.hit(r, Interval::new(ray_t.min, closest_so_far)) {
-                closest_so_far = rec.t;
-                hit_anything = Some(rec);
-            }
+    // `--sky <elevation>` lights the scene with a daylight sky and a sun that many degrees above
+    // the horizon.
+    if let Some(elevation) = arg_value(args, "--sky").and_then(|n| n.parse::<f64>().ok()) {
+        let (sin, cos) = elevation.to_radians().sin_cos();
+        params.background = Background::Sky(ProceduralSky {
+            sun_dir: vec3(0.6 * cos, sin, 0.8 * cos),
+            sun_intensity: 15000.0,
+            sun_angular_radius: 0.5,
+            turbidity: 2.0,
+        });
+    }
+    // `--exposure <n>` brightens (n > 1) or darkens (n < 1) the image.
+    if let Some(exposure) = arg_value(args, "--exposure").and_then(|n| n.parse().ok()) {
+        params.exposure = exposure;
+    }
+    // `--tone-map <reinhard|aces>` rolls off highlights instead of clipping them.
+    params.tone_map = match arg_value(args, "--tone-map") {
+        Some("reinhard") => ToneMap::Reinhard,
+        Some("aces") => ToneMap::AcesFilmic,
+        _ => ToneMap::Clamp,
+    };
+    // `--gamma <srgb|n>` picks the sRGB curve or another gamma exponent than 2.0.
+    match arg_value(args, "--gamma") {
+        Some("srgb") => params.gamma = GammaMode::Srgb,
+        Some(gamma) => match gamma.parse() {
+            Ok(gamma) => params.gamma = GammaMode::Power(gamma),
+            Err(_) => eprintln!("Ignoring invalid gamma {gamma}"),
+        },
+        None => {}
+    }
+    // `--no-progress` keeps stderr quiet, e.g. when piping or in CI.
+    params.progress = !args.iter().any(|arg| arg == "--no-progress");
+    // `--report` prints ray counts and timings to stderr after rendering.
+    params.report_stats = args.iter().any(|arg| arg == "--report");
+    // `--rolling-shutter <duration>` exposes each row for that fraction of the shutter interval,
+    // one after the other.
+    if let Some(duration) = arg_value(args, "--rolling-shutter").and_then(|n| n.parse().ok()) {
+        params.shutter = Shutter::Rolling { duration };
+    }
+    // `--stratified` jitters samples on a grid instead of using the low-discrepancy sequence.
+    if args.iter().any(|arg| arg == "--stratified") {
+        params.pixel_sampling = PixelSampling::Stratified;
+    }
+    // `--adaptive <threshold>` stops sampling pixels once their variance is below the threshold.
+    if let Some(variance_threshold) = arg_value(args, "--adaptive").and_then(|n| n.parse().ok()) {
+        params.refinement = RefinementStrategy::Adaptive {
+            min_samples: (params.samples_per_pixel / 8).max(4),
+            variance_threshold,
+        };
+    }
+    // `--tile-order <spiral|hilbert|random>` changes the order renders finish tiles in.
+    params.tile_order = match arg_value(args, "--tile-order") {
+        Some("spiral") => TileOrder::Spiral,
+        Some("hilbert") => TileOrder::Hilbert,
+        Some("random") => TileOrder::Random,
+        _ => TileOrder::Scanline,
+    };
+    // `--clamp <luminance>` caps the brightness of single samples to get rid of fireflies.
+    params.clamp_luminance = arg_value(args, "--clamp").and_then(|n| n.parse().ok());
+    // `--spectral` splits white light into colors in dispersive glass.
+    params.spectral = args.iter().any(|arg| arg == "--spectral");
+    // `--heatmap` outputs how many samples each pixel took instead of the image.
+    params.heatmap = args.iter().any(|arg| arg == "--heatmap");
+    // `--debug <normals|depth|bvh|ao>` shows the geometry instead of path tracing it.
+    params.debug = match arg_value(args, "--debug") {
+        Some("normals") => DebugMode::Normals,
+        Some("depth") => DebugMode::Depth {
+            max_distance: 2.0 * (params.lookfrom - params.lookat).length(),
+        },
+        Some("bvh") => DebugMode::BvhNodes { max_nodes: 64 },
+        Some("ao") => DebugMode::AmbientOcclusion(AoParams {
+            samples: 16,
+            max_distance: 0.1 * (params.lookfrom - params.lookat).length(),
+        }),
+        Some(mode) => {
+            eprintln!("Ignoring unknown debug mode {mode}");
+            DebugMode::Off
+        }
+        None => DebugMode::Off,
+    };
+    // `--region <x0,y0,x1,y1>` renders only that window of the image.
+    if let Some(region) = arg_value(args, "--region") {
+        match region
+            .split(',')
+            .map(|n| n.trim().parse())
+            .collect::<Result<Vec<i32>, _>>()
+            .as_deref()
+        {
+            Ok(&[x0, y0, x1, y1]) => params.region = Some((x0, y0, x1, y1)),
+            _ => eprintln!("Ignoring invalid region {region}"),
         }
-
-        hit_anything
     }
-}
 
-#[derive(Clone, Copy, Default)]
-struct Interval {
-    min: f64,
-    max: f64,
+    (world, params)
 }
 
-impl Interval {
-    fn new(min: f64, max: f64) -> Self {
-        Self { min, max }
-    }
+/// The final scene of the book: a field of small random spheres around three big ones.
+fn random_spheres(seed: u64, motion_blur: bool) -> (HittableList, CameraParams) {
+    let mut world = HittableList::new();
 
-    fn surrounds(&self, x: f64) -> bool {
-        self.min < x && x < self.max
-    }
+    let checker =
+        CheckerTexture::from_colors(0.32, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9));
+    let mat_ground = Arc::new(Lambertian::from_texture(Arc::new(checker)));
+    world.add(Box::new(Sphere::new(
+        vec3(0.0, -1000.0, -1.0),
+        1000.0,
+        mat_ground,
+    )));
 
-    fn clamp(&self, x: f64) -> f64 {
-        if x < self.min {
-            return self.min;
-        }
-        if x > self.max {
-            return self.max;
-        }
+    let mut rng = StdRng::seed_from_u64(seed);
 
-        x
-    }
-}
+    for a in -11..11 {
+        for b in -11..11 {
+            let center = Point3::new(
+                a as f64 + 0.9 * rng.gen::<f64>(),
+                0.2,
+                b as f64 + 0.9 * rng.gen::<f64>(),
+            );
 
-pub fn random_vec3() -> Vec3 {
-    vec3(rand::random(), rand::random(), rand::random())
-}
+            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                let num = rng.gen::<f64>();
+                let mat: Arc<dyn Material> = if num < 0.8 {
+                    let albedo = random_vec3(&mut rng) * random_vec3(&mut rng);
 
-pub fn random_vec3_range(r: Range<f64>) -> Vec3 {
-    let mut rng = rand::thread_rng();
-    vec3(
-        rng.gen_range(r.clone()),
-        rng.gen_range(r.clone()),
-        rng.gen_range(r),
-    )
-}
+                    Arc::new(Lambertian::new(albedo))
+                } else if num < 0.95 {
+                    let albedo = Color::new(0.5, 1.0, rng.gen());
+                    let fuzz = rng.gen_range((0.0)..0.5);
+                    Arc::new(Metal { albedo, fuzz })
+                } else {
+                    Arc::new(Dielectric::new(1.5))
+                };
 
-pub fn random_vec3_unit() -> Vec3 {
-    loop {
-        let p = random_vec3_range((-1.0)..1.0);
-        if p.length_squared() < 1.0 {
-            return p.normalize();
+                // `--motion-blur` makes the diffuse spheres bounce up while the shutter is open.
+                let sphere = if motion_blur && num < 0.8 {
+                    let center_end = center + vec3(0.0, rng.gen_range(0.0..0.5), 0.0);
+                    Sphere::moving(center, center_end, 0.2, mat)
+                } else {
+                    Sphere::new(center, 0.2, mat)
+                };
+                world.add(Box::new(sphere));
+            }
         }
     }
-}
 
-pub fn random_vec3_on_hempishere(normal: &Vec3) -> Vec3 {
-    let on_unit_sphere = random_vec3_unit();
-    if normal.dot(on_unit_sphere) > 0.0 {
-        // In the same hemisphere as the normal
-        on_unit_sphere
-    } else {
-        -on_unit_sphere
-    }
+    // The large spheres have IDs for the `--ids` pass.
+    let mat1 = Arc::new(Dielectric::new(1.5));
+    world.add(Box::new(Identified {
+        id: 1,
+        object: Box::new(Sphere::new(vec3(0.0, 1.0, 0.0), 1.0, mat1)),
+    }));
+
+    let mat2 = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
+    world.add(Box::new(Identified {
+        id: 2,
+        object: Box::new(Sphere::new(vec3(-4.0, 1.0, 0.0), 1.0, mat2)),
+    }));
+
+    let mat3 = Arc::new(Metal {
+        albedo: Color::new(0.7, 0.6, 0.5),
+        fuzz: 0.0,
+    });
+    world.add(Box::new(Identified {
+        id: 3,
+        object: Box::new(Sphere::new(vec3(4.0, 1.0, 0.0), 1.0, mat3)),
+    }));
+
+    let params = CameraParams {
+        aspect_ratio: 16.0 / 9.0,
+        image_width: 600,
+        samples_per_pixel: 100,
+        max_depth: 25,
+        vfov: 20.0,
+        lookfrom: Point3::new(13.0, 2.0, 3.0),
+        lookat: Point3::ZERO,
+        vup: vec3(0., 1., 0.),
+        defocus_angle: 0.6,
+        focus_dist: 10.0,
+        focus_on: None,
+        seed,
+        ..CameraParams::default()
+    };
+
+    (world, params)
 }
-pub fn random_vec3_on_unit_disc() -> Vec3 {
-    let mut rng = rand::thread_rng();
 
-    loop {
-        let p = vec3(rng.gen_range((-1.0)..1.0), rng.gen_range((-1.0)..1.0), 0.0);
-        if p.length_squared() < 1.0 {
-            return p;
-        }
-    }
+/// Returns the argument following `flag`, if any.
+fn arg_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|idx| args.get(idx + 1))
+        .map(String::as_str)
 }
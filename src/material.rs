@@ -1,28 +1,182 @@
 use glam::DVec3 as Vec3;
-use rand::random;
+use rand::{Rng, RngCore};
 
-use crate::{color::Color, random_vec3_unit, HitRecord, Ray};
+use std::{f64::consts::PI, sync::Arc};
 
-pub trait Material {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)>;
+use crate::{
+    color::Color,
+    pdf::{CosinePdf, Onb, Pdf, SpherePdf},
+    random_vec3_unit,
+    texture::{SolidColor, Texture},
+    HitRecord, Ray,
+};
+
+/// Materials are shared between objects and threads through `Arc`, so they must be
+/// `Send + Sync`.
+pub trait Material: Send + Sync {
+    /// Returns how the ray scatters off the surface, or `None` if the ray is absorbed.
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut dyn RngCore) -> Option<ScatterRecord>;
+
+    /// Returns the density with which the material scatters `r_in` into `scattered`. Only
+    /// materials that scatter with `Scattered::Pdf` need to implement this.
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        0.0
+    }
+
+    /// The color of the surface where `rec` hit it, regardless of lighting, for the albedo pass.
+    /// Materials without one, like glass and lights, are white.
+    fn base_albedo(&self, _rec: &HitRecord) -> Color {
+        Color::ONE
+    }
+
+    /// Light given off towards the origin of `r_in` by the material where `rec` hit it, in
+    /// addition to whatever it scatters.
+    fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Color {
+        Color::ZERO
+    }
+
+    /// Whether the material scatters light differently depending on its `Ray::wavelength`, so
+    /// spectral renders trace each color channel through it on its own.
+    fn is_dispersive(&self) -> bool {
+        false
+    }
+}
+
+pub struct ScatterRecord {
+    pub attenuation: Color,
+    pub scattered: Scattered,
+}
+
+impl ScatterRecord {
+    /// Scatters into exactly `scattered`, like a mirror or glass does.
+    pub fn specular(scattered: Ray, attenuation: Color) -> Self {
+        Self {
+            attenuation,
+            scattered: Scattered::Ray(scattered),
+        }
+    }
+
+    /// Scatters into directions distributed by `pdf`.
+    pub fn pdf(pdf: Box<dyn Pdf>, attenuation: Color) -> Self {
+        Self {
+            attenuation,
+            scattered: Scattered::Pdf(pdf),
+        }
+    }
+}
+
+pub enum Scattered {
+    /// A single ray that's followed as is, with the attenuation applied directly.
+    Ray(Ray),
+    /// Directions are drawn from the density, which the renderer may mix with sampling towards
+    /// the lights. The attenuation is then weighed by `Material::scattering_pdf` over the
+    /// density the direction was actually drawn with.
+    Pdf(Box<dyn Pdf>),
 }
 
 pub struct Lambertian {
-    pub albedo: Color,
+    pub tex: Arc<dyn Texture>,
+}
+
+impl Lambertian {
+    pub fn new(albedo: Color) -> Self {
+        Self::from_texture(Arc::new(SolidColor(albedo)))
+    }
+
+    pub fn from_texture(tex: Arc<dyn Texture>) -> Self {
+        Self { tex }
+    }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
-        let mut scatter_direction = rec.normal + random_vec3_unit();
+    fn base_albedo(&self, rec: &HitRecord) -> Color {
+        self.tex.value_at(rec)
+    }
+
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        rec: &HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
+        let attenuation = self.tex.value_at(rec);
+
+        Some(ScatterRecord::pdf(
+            Box::new(CosinePdf::new(rec.normal)),
+            attenuation,
+        ))
+    }
 
-        if vec3_near_zero(&scatter_direction) {
-            scatter_direction = rec.normal;
+    fn scattering_pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let cos_theta = rec.normal.dot(scattered.direction().normalize());
+
+        f64::max(0.0, cos_theta / PI)
+    }
+}
+
+/// A rough matte surface, like clay, plaster or the moon (Oren–Nayar reflectance). Its facets
+/// face the light more the closer the viewer is to the light, so it looks flatter than a
+/// `Lambertian` lit from the side and brightens towards grazing angles.
+pub struct OrenNayar {
+    pub albedo: Arc<dyn Texture>,
+    /// Standard deviation of the facet angles in radians. 0.0 is `Lambertian`, around 0.5 is
+    /// very rough.
+    pub roughness: f64,
+}
+
+impl OrenNayar {
+    pub fn new(albedo: Color, roughness: f64) -> Self {
+        Self {
+            albedo: Arc::new(SolidColor(albedo)),
+            roughness,
         }
+    }
+}
 
-        let scattered = Ray::new(rec.p, scatter_direction);
-        let attenuation = self.albedo;
+impl Material for OrenNayar {
+    fn base_albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value_at(rec)
+    }
+
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        rec: &HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
+        let attenuation = self.albedo.value_at(rec);
 
-        Some((scattered, attenuation))
+        Some(ScatterRecord::pdf(
+            Box::new(CosinePdf::new(rec.normal)),
+            attenuation,
+        ))
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let to_viewer = -r_in.direction().normalize();
+        let to_light = scattered.direction().normalize();
+        let cos_in = rec.normal.dot(to_light);
+        let cos_out = rec.normal.dot(to_viewer).clamp(0.0, 1.0);
+        if cos_in <= 0.0 {
+            return 0.0;
+        }
+
+        let sigma2 = self.roughness * self.roughness;
+        let a = 1.0 - 0.5 * sigma2 / (sigma2 + 0.33);
+        let b = 0.45 * sigma2 / (sigma2 + 0.09);
+
+        // Cosine of the azimuth between the directions, in the plane of the surface.
+        let in_plane = (to_light - cos_in * rec.normal).normalize_or_zero();
+        let out_plane = (to_viewer - cos_out * rec.normal).normalize_or_zero();
+        let cos_phi = in_plane.dot(out_plane).max(0.0);
+
+        // sin(alpha) * tan(beta), with alpha the larger and beta the smaller of the angles of
+        // the two directions from the normal.
+        let sin_in = (1.0 - cos_in * cos_in).max(0.0).sqrt();
+        let sin_out = (1.0 - cos_out * cos_out).max(0.0).sqrt();
+        let sin_alpha_tan_beta = sin_in * sin_out / cos_in.max(cos_out);
+
+        cos_in / PI * (a + b * cos_phi * sin_alpha_tan_beta)
     }
 }
 
@@ -32,13 +186,202 @@ pub struct Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
-        let mut reflected = vec3_reflect(r_in.direction, rec.normal);
-        reflected = reflected.normalize() + self.fuzz.min(1.0) * random_vec3_unit();
-        let scattered = Ray::new(rec.p, reflected);
+    fn base_albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut dyn RngCore) -> Option<ScatterRecord> {
+        let mut reflected = vec3_reflect(r_in.direction(), rec.normal);
+        reflected = reflected.normalize() + self.fuzz.clamp(0.0, 1.0) * random_vec3_unit(rng);
+        let scattered = Ray::new(rec.p, reflected).with_time(r_in.time);
         let attenuation = self.albedo;
 
-        Some((scattered, attenuation))
+        // Fuzz can push grazing reflections below the surface, which absorbs them.
+        if scattered.direction().dot(rec.normal) <= 0.0 {
+            return None;
+        }
+
+        Some(ScatterRecord::specular(scattered, attenuation))
+    }
+}
+
+/// A metal whose reflectance follows the Fresnel equations for conductors, so it brightens
+/// towards white at grazing angles. `eta` and `k` are the real and imaginary parts of the
+/// refractive index per color channel, e.g. `(0.143, 0.374, 1.442)` and `(3.983, 2.386, 1.603)`
+/// for gold.
+pub struct ConductorMetal {
+    pub eta: Color,
+    pub k: Color,
+    pub fuzz: f64,
+}
+
+impl ConductorMetal {
+    /// Unpolarized reflectance of light arriving at `cos_theta` to the normal.
+    fn fresnel(&self, cos_theta: f64) -> Color {
+        let cos2 = cos_theta * cos_theta;
+        let sin2 = 1.0 - cos2;
+        let eta2 = self.eta * self.eta;
+        let k2 = self.k * self.k;
+
+        let t0 = eta2 - k2 - Color::splat(sin2);
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).powf(0.5);
+        let a = (0.5 * (a2_plus_b2 + t0)).max(Color::ZERO).powf(0.5);
+
+        let t1 = a2_plus_b2 + Color::splat(cos2);
+        let t2 = 2.0 * cos_theta * a;
+        let rs = (t1 - t2) / (t1 + t2);
+
+        let t3 = cos2 * a2_plus_b2 + Color::splat(sin2 * sin2);
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+
+        0.5 * (rs + rp)
+    }
+}
+
+impl Material for ConductorMetal {
+    fn base_albedo(&self, _rec: &HitRecord) -> Color {
+        self.fresnel(1.0)
+    }
+
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut dyn RngCore) -> Option<ScatterRecord> {
+        let unit_direction = r_in.direction().normalize();
+        let cos_theta = rec.normal.dot(-unit_direction).clamp(0.0, 1.0);
+
+        let reflected = vec3_reflect(unit_direction, rec.normal)
+            + self.fuzz.clamp(0.0, 1.0) * random_vec3_unit(rng);
+        let scattered = Ray::new(rec.p, reflected).with_time(r_in.time);
+
+        if scattered.direction().dot(rec.normal) <= 0.0 {
+            return None;
+        }
+
+        Some(ScatterRecord::specular(scattered, self.fresnel(cos_theta)))
+    }
+}
+
+/// A microfacet material for anything from matte plastic to polished metal. Highlights follow
+/// the GGX distribution of microfacet normals, whose spread grows with `roughness`, and
+/// reflect more at grazing angles (Fresnel-Schlick). Non-metals also reflect `albedo`
+/// diffusely, metals tint their reflections with it instead.
+pub struct Glossy {
+    pub albedo: Color,
+    /// 0.0 for a mirror, 1.0 for a very rough surface
+    pub roughness: f64,
+    /// 0.0 for a dielectric like plastic, 1.0 for a metal
+    pub metallic: f64,
+}
+
+impl Glossy {
+    /// Fresnel-Schlick reflectance. Dielectrics reflect about 4% head-on.
+    fn fresnel(&self, cosine: f64) -> Color {
+        let f0 = Color::splat(0.04).lerp(self.albedo, self.metallic.clamp(0.0, 1.0));
+        f0 + (Color::ONE - f0) * (1.0 - cosine).clamp(0.0, 1.0).powi(5)
+    }
+
+    /// Smith's shadowing-masking term for one direction at `cosine` to the normal.
+    fn smith_g1(cosine: f64, alpha: f64) -> f64 {
+        let alpha2 = alpha * alpha;
+        2.0 * cosine / (cosine + (alpha2 + (1.0 - alpha2) * cosine * cosine).sqrt())
+    }
+}
+
+impl Material for Glossy {
+    fn base_albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut dyn RngCore) -> Option<ScatterRecord> {
+        let metallic = self.metallic.clamp(0.0, 1.0);
+        let view = -r_in.direction().normalize();
+        let cos_view = rec.normal.dot(view).max(1e-6);
+
+        // Pick the specular or the diffuse lobe, and weigh the result by the odds of picking it.
+        // Metals have no diffuse lobe.
+        let specular_odds = 0.5 + 0.5 * metallic;
+        if rng.gen::<f64>() >= specular_odds {
+            let diffuse = self.albedo * (1.0 - metallic) * (Color::ONE - self.fresnel(cos_view));
+            return Some(ScatterRecord::pdf(
+                Box::new(CosinePdf::new(rec.normal)),
+                diffuse / (1.0 - specular_odds),
+            ));
+        }
+
+        // Sample a microfacet normal from the GGX distribution, and mirror the ray about it.
+        let alpha = (self.roughness.clamp(0.0, 1.0) * self.roughness.clamp(0.0, 1.0)).max(1e-3);
+        let (r1, r2): (f64, f64) = (rng.gen(), rng.gen());
+        let phi = 2.0 * PI * r1;
+        let cos_half = ((1.0 - r2) / (1.0 + (alpha * alpha - 1.0) * r2)).sqrt();
+        let sin_half = (1.0 - cos_half * cos_half).max(0.0).sqrt();
+        let half = Onb::new(rec.normal).transform(Vec3::new(
+            sin_half * phi.cos(),
+            sin_half * phi.sin(),
+            cos_half,
+        ));
+
+        let direction = vec3_reflect(-view, half);
+        let cos_light = rec.normal.dot(direction);
+        let cos_view_half = view.dot(half);
+        if cos_light <= 0.0 || cos_view_half <= 0.0 {
+            return None;
+        }
+
+        // With half-vectors drawn in proportion to D * cos(half), the BRDF times the cosine over
+        // the density reduces to F * G * (v.h) / ((n.v) * (n.h)).
+        let g = Self::smith_g1(cos_view, alpha) * Self::smith_g1(cos_light, alpha);
+        let weight = g * cos_view_half / (cos_view * cos_half.max(1e-6));
+        let attenuation = self.fresnel(cos_view_half) * weight / specular_odds;
+
+        let scattered = Ray::new(rec.p, direction).with_time(r_in.time);
+        Some(ScatterRecord::specular(scattered, attenuation))
+    }
+
+    fn scattering_pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        // Only used for the diffuse lobe.
+        let cos_theta = rec.normal.dot(scattered.direction().normalize());
+        f64::max(0.0, cos_theta / PI)
+    }
+}
+
+/// A metal with fuzz that differs along and across the surface, like brushed aluminum.
+pub struct BrushedMetal {
+    pub albedo: Color,
+    /// Fuzz along the direction of `tangent` on the surface
+    pub fuzz_u: f64,
+    /// Fuzz across the direction of `tangent` on the surface
+    pub fuzz_v: f64,
+    /// Direction the surface is brushed in. It's projected onto the surface at every hit, so it
+    /// doesn't need to be tangent itself, only not parallel to the normal.
+    pub tangent: Vec3,
+}
+
+impl Material for BrushedMetal {
+    fn base_albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut dyn RngCore) -> Option<ScatterRecord> {
+        // Build the surface frame from the brushing direction, or any frame if it's parallel to
+        // the normal.
+        let bitangent = rec.normal.cross(self.tangent).try_normalize();
+        let (tangent, bitangent) = match bitangent {
+            Some(bitangent) => (bitangent.cross(rec.normal), bitangent),
+            None => {
+                let uvw = Onb::new(rec.normal);
+                (uvw.u, uvw.v)
+            }
+        };
+
+        let reflected = vec3_reflect(r_in.direction(), rec.normal).normalize();
+        let perturbation = self.fuzz_u.clamp(0.0, 1.0) * rng.gen_range(-1.0..1.0) * tangent
+            + self.fuzz_v.clamp(0.0, 1.0) * rng.gen_range(-1.0..1.0) * bitangent;
+        let scattered = Ray::new(rec.p, reflected + perturbation).with_time(r_in.time);
+
+        if scattered.direction().dot(rec.normal) <= 0.0 {
+            return None;
+        }
+
+        Some(ScatterRecord::specular(scattered, self.albedo))
     }
 }
 
@@ -46,9 +389,54 @@ pub struct Dielectric {
     /// Refractive index in vacuum or air, or the ratio of the material's refractive index over
     /// the refractive index of the enclosing media
     pub refraction_index: f64,
+    /// Color of light passing through or reflecting off the surface, e.g. the pattern of stained
+    /// glass. Clear glass leaves it white.
+    pub tint: Option<Arc<dyn Texture>>,
+    /// Coefficient B of Cauchy's equation in µm², by which the refractive index grows towards
+    /// shorter wavelengths, with `refraction_index` being the index at 550 nm. About 0.004 for
+    /// crown glass, 0.01 for flint glass and 0.0 for none. Only rays with a `wavelength` disperse,
+    /// see `CameraParams::spectral`.
+    pub dispersion: f64,
 }
 
 impl Dielectric {
+    /// Clear glass.
+    pub fn new(refraction_index: f64) -> Self {
+        Self {
+            refraction_index,
+            tint: None,
+            dispersion: 0.0,
+        }
+    }
+
+    /// Sets the Cauchy coefficient that spreads the refractive index over wavelengths.
+    pub fn with_dispersion(self, dispersion: f64) -> Self {
+        Self { dispersion, ..self }
+    }
+
+    /// Returns the refractive index for light of `wavelength` nanometers, or for all colors.
+    fn refraction_index_at(&self, wavelength: Option<f64>) -> f64 {
+        const REFERENCE_WAVELENGTH: f64 = 0.55;
+
+        match wavelength {
+            Some(nanometers) => {
+                let micrometers = nanometers / 1000.0;
+                self.refraction_index
+                    + self.dispersion
+                        * (1.0 / micrometers.powi(2) - 1.0 / REFERENCE_WAVELENGTH.powi(2))
+            }
+            None => self.refraction_index,
+        }
+    }
+
+    /// Sets the texture that tints the light scattered at the surface.
+    pub fn with_tint(self, tint: Arc<dyn Texture>) -> Self {
+        Self {
+            tint: Some(tint),
+            ..self
+        }
+    }
+
     fn reflectance(cosine: f64, refraction_index: f64) -> f64 {
         // Use Schlick's approximation for reflectance.
         let mut r0 = (1.0 - refraction_index) / (1.0 + refraction_index);
@@ -59,29 +447,291 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut dyn RngCore) -> Option<ScatterRecord> {
+        let refraction_index = self.refraction_index_at(r_in.wavelength);
         let ri = if rec.front_face {
-            1.0 / self.refraction_index
+            1.0 / refraction_index
         } else {
-            self.refraction_index
+            refraction_index
         };
 
-        let unit_direction = r_in.direction.normalize();
-        let cos_theta = f64::min(rec.normal.dot(-unit_direction), 1.0);
-        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let unit_direction = r_in.direction().normalize();
+        let cos_theta = rec.normal.dot(-unit_direction).clamp(-1.0, 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
 
         let cannot_refract = ri * sin_theta > 1.0;
 
-        let direction = if cannot_refract || Self::reflectance(cos_theta, ri) > random() {
+        let direction = if cannot_refract || Self::reflectance(cos_theta, ri) > rng.gen() {
             vec3_reflect(unit_direction, rec.normal)
         } else {
             vec3_refract(unit_direction, rec.normal, ri)
         };
 
-        let attenuation = Color::ONE;
-        let scattered = Ray::new(rec.p, direction);
+        let attenuation = match &self.tint {
+            Some(tint) => tint.value_at(rec),
+            None => Color::ONE,
+        };
+        let scattered = Ray::new(rec.p, direction).with_time(r_in.time);
+
+        Some(ScatterRecord::specular(scattered, attenuation))
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.dispersion != 0.0
+    }
+}
+
+/// Glass that absorbs light on its way through, so thick parts are tinted more deeply than thin
+/// ones (Beer–Lambert law).
+pub struct ColoredDielectric {
+    pub refraction_index: f64,
+    /// The color the glass tints light towards. Components of 1.0 aren't absorbed at all.
+    pub attenuation: Color,
+    /// How quickly light is absorbed per world unit traveled inside the glass
+    pub absorption: f64,
+}
+
+impl Material for ColoredDielectric {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut dyn RngCore) -> Option<ScatterRecord> {
+        let glass = Dielectric::new(self.refraction_index);
+        let mut srec = glass.scatter(r_in, rec, rng)?;
+
+        // Hitting the inside of the surface means the ray went through the glass since it
+        // entered, or was last reflected inside.
+        if !rec.front_face {
+            let distance = rec.t * r_in.length_squared().sqrt();
+            let absorbance = self.absorption * distance * (Color::ONE - self.attenuation);
+            srec.attenuation *= (-absorbance).exp();
+        }
+
+        Some(srec)
+    }
+}
+
+/// An emissive material that doesn't reflect any light. Components of `emit` above 1.0 make for
+/// lights bright enough to illuminate their surroundings.
+///
+/// Only the front of the surface emits, the side its outward normal points to, so e.g. a quad
+/// can light a room without lighting the ceiling behind it. Wrap the object in `FlipFace` to make
+/// the other side emit instead.
+pub struct DiffuseLight {
+    pub emit: Color,
+}
+
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        _rec: &HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
+        None
+    }
+
+    fn emitted(&self, _r_in: &Ray, rec: &HitRecord) -> Color {
+        if !rec.front_face {
+            return Color::ZERO;
+        }
+
+        self.emit
+    }
+}
+
+/// An emissive material that only shines within a cone around `axis`, like a stage light.
+/// Emission is full inside `inner_angle`, fades out towards `outer_angle` and is zero beyond it.
+pub struct SpotLight {
+    pub emit: Color,
+    /// Direction the light points in
+    pub axis: Vec3,
+    cos_inner: f64,
+    cos_outer: f64,
+    falloff: f64,
+}
+
+impl SpotLight {
+    /// Creates a spot light with the cone half-angles in degrees. Higher `falloff` exponents
+    /// make the edge of the cone fade out faster.
+    pub fn new(emit: Color, axis: Vec3, inner_angle: f64, outer_angle: f64, falloff: f64) -> Self {
+        let outer_angle = outer_angle.max(inner_angle);
+        Self {
+            emit,
+            axis: axis.normalize(),
+            cos_inner: inner_angle.to_radians().cos(),
+            cos_outer: outer_angle.to_radians().cos(),
+            falloff,
+        }
+    }
+}
+
+impl Material for SpotLight {
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        _rec: &HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
+        None
+    }
+
+    fn emitted(&self, r_in: &Ray, _rec: &HitRecord) -> Color {
+        let cos_theta = -r_in.direction().normalize().dot(self.axis);
+        if cos_theta >= self.cos_inner {
+            return self.emit;
+        }
+        if cos_theta <= self.cos_outer {
+            return Color::ZERO;
+        }
+
+        // Smoothstep across the edge of the cone.
+        let t = (cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer);
+        let smooth = t * t * (3.0 - 2.0 * t);
+        self.emit * smooth.powf(self.falloff)
+    }
+}
+
+/// A diffuse surface that also glows, like a fluorescent panel.
+pub struct GlowingLambertian {
+    pub albedo: Arc<dyn Texture>,
+    pub emit: Arc<dyn Texture>,
+}
+
+impl GlowingLambertian {
+    pub fn new(albedo: Color, emit: Color) -> Self {
+        Self {
+            albedo: Arc::new(SolidColor(albedo)),
+            emit: Arc::new(SolidColor(emit)),
+        }
+    }
+}
+
+impl Material for GlowingLambertian {
+    fn base_albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value_at(rec)
+    }
+
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        rec: &HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
+        let attenuation = self.albedo.value_at(rec);
+
+        Some(ScatterRecord::pdf(
+            Box::new(CosinePdf::new(rec.normal)),
+            attenuation,
+        ))
+    }
+
+    fn scattering_pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let cos_theta = rec.normal.dot(scattered.direction().normalize());
+
+        f64::max(0.0, cos_theta / PI)
+    }
+
+    fn emitted(&self, _r_in: &Ray, rec: &HitRecord) -> Color {
+        self.emit.value_at(rec)
+    }
+}
+
+/// Scatters light equally in every direction, like the particles of smoke or fog.
+pub struct Isotropic {
+    pub albedo: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Self {
+        Self::from_texture(Arc::new(SolidColor(albedo)))
+    }
+
+    pub fn from_texture(albedo: Arc<dyn Texture>) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Material for Isotropic {
+    fn base_albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value_at(rec)
+    }
+
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        rec: &HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
+        let attenuation = self.albedo.value_at(rec);
+
+        Some(ScatterRecord::pdf(Box::new(SpherePdf), attenuation))
+    }
+
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+}
+
+/// Bumps and grooves the geometry doesn't have: `material` shades with its normal bent by a
+/// tangent-space normal map. The red, green and blue channels of the map are the components of
+/// the normal along the surface tangent, where `u` increases, the bitangent, roughly where `v`
+/// increases, and the geometric normal, mapped from [-1,1] to [0,1] the way normal maps are
+/// usually stored. Image maps aren't gamma encoded, so load them with
+/// `ImageTexture::load_linear`.
+///
+/// Shapes without a tangent get an arbitrary one, which only suits maps without a direction,
+/// like noise. Where the map bends the normal away from the viewer the geometric one is kept.
+pub struct NormalMapped {
+    pub material: Arc<dyn Material>,
+    pub normal_map: Arc<dyn Texture>,
+}
+
+impl NormalMapped {
+    /// Returns a copy of `rec` with the normal from the normal map.
+    fn perturbed(&self, r_in: &Ray, rec: &HitRecord) -> HitRecord {
+        let outward_normal = if rec.front_face {
+            rec.normal
+        } else {
+            -rec.normal
+        };
+
+        // The tangent frame, with the tangent made perpendicular to the normal.
+        let tangent = (rec.tangent - rec.tangent.dot(outward_normal) * outward_normal)
+            .try_normalize()
+            .unwrap_or_else(|| Onb::new(outward_normal).u);
+        let bitangent = outward_normal.cross(tangent);
+
+        let mapped = 2.0 * self.normal_map.value_at(rec) - Color::ONE;
+        let normal = (mapped.x * tangent + mapped.y * bitangent + mapped.z * outward_normal)
+            .try_normalize()
+            .map(|normal| if rec.front_face { normal } else { -normal });
+
+        let mut rec = rec.clone();
+        if let Some(normal) = normal.filter(|normal| normal.dot(r_in.direction()) < 0.0) {
+            rec.normal = normal;
+        }
+        rec
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut dyn RngCore) -> Option<ScatterRecord> {
+        self.material.scatter(r_in, &self.perturbed(r_in, rec), rng)
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        self.material
+            .scattering_pdf(r_in, &self.perturbed(r_in, rec), scattered)
+    }
+
+    fn base_albedo(&self, rec: &HitRecord) -> Color {
+        self.material.base_albedo(rec)
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        self.material.emitted(r_in, rec)
+    }
 
-        Some((scattered, attenuation))
+    fn is_dispersive(&self) -> bool {
+        self.material.is_dispersive()
     }
 }
 
@@ -92,7 +742,8 @@ pub fn vec3_reflect(vec: Vec3, n: Vec3) -> Vec3 {
 pub fn vec3_refract(uv: Vec3, n: Vec3, etai_over_etat: f64) -> Vec3 {
     let cos_theta = f64::min(n.dot(-uv), 1.0);
     let r_out_perp = etai_over_etat * (uv + cos_theta * n);
-    let r_out_parallel = -((1.0 - r_out_perp.length_squared()).abs()).sqrt() * n;
+    // Rounding can push the perpendicular part just past unit length at grazing angles.
+    let r_out_parallel = -(1.0 - r_out_perp.length_squared()).max(0.0).sqrt() * n;
 
     r_out_perp + r_out_parallel
 }
@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use crate::{aabb::Aabb, material::Material, vec3, HitRecord, Hittable, Interval, Ray, Vec3};
+
+/// A volume of constant density, like smoke or fog, filling a closed `boundary`.
+///
+/// Rays passing through the volume scatter at a random distance that gets shorter the denser
+/// the medium is. The boundary may be concave, and rays may start inside it.
+pub struct ConstantMedium {
+    pub boundary: Box<dyn Hittable>,
+    pub neg_inv_density: f64,
+    pub phase: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Box<dyn Hittable>, density: f64, phase: Arc<dyn Material>) -> Self {
+        Self {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase,
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        const EPSILON: f64 = 0.0001;
+
+        let ray_length = r.direction().length();
+        let mut hit_distance = self.neg_inv_density * random_for_ray(r).ln();
+
+        // Walk through the boundary one entry and exit pair at a time, so a ray leaving a
+        // concave volume can still scatter where it enters again further along.
+        let mut search = Interval::UNIVERSE;
+        loop {
+            let rec1 = self.boundary.hit(r, search)?;
+            let rec2 = self
+                .boundary
+                .hit(r, Interval::new(rec1.t + EPSILON, f64::INFINITY))?;
+
+            // Only the part of the segment within `ray_t` counts, which also covers rays that
+            // start inside the volume.
+            let t_enter = rec1.t.max(ray_t.min);
+            let t_exit = rec2.t.min(ray_t.max);
+
+            if t_enter < t_exit {
+                let distance_inside_boundary = (t_exit - t_enter) * ray_length;
+
+                if hit_distance <= distance_inside_boundary {
+                    let t = t_enter + hit_distance / ray_length;
+
+                    return Some(HitRecord {
+                        p: r.at(t),
+                        // Arbitrary, a medium has no surface to face.
+                        normal: vec3(1.0, 0.0, 0.0),
+                        mat: self.phase.clone(),
+                        t,
+                        u: 0.0,
+                        v: 0.0,
+                        tangent: Vec3::ZERO,
+                        front_face: true,
+                        object_id: None,
+                        motion: Vec3::ZERO,
+                    });
+                }
+
+                hit_distance -= distance_inside_boundary;
+            }
+
+            if rec2.t >= ray_t.max {
+                return None;
+            }
+            search.min = rec2.t + EPSILON;
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.boundary.bounding_box()
+    }
+}
+
+/// Returns a number in (0,1] derived from the ray, so renders stay deterministic even though
+/// `hit` has no random number generator to draw from.
+fn random_for_ray(r: &Ray) -> f64 {
+    let mut h = 0x243f_6a88_85a3_08d3_u64;
+    for x in [
+        r.origin.x,
+        r.origin.y,
+        r.origin.z,
+        r.direction().x,
+        r.direction().y,
+        r.direction().z,
+        r.time,
+    ] {
+        // SplitMix64 finalizer over each component.
+        h = (h ^ x.to_bits()).wrapping_add(0x9e37_79b9_7f4a_7c15);
+        h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        h ^= h >> 31;
+    }
+
+    ((h >> 11) as f64 + 1.0) / (1u64 << 53) as f64
+}
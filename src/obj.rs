@@ -0,0 +1,253 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+    sync::Arc,
+};
+
+use crate::{
+    color::Color,
+    material::{Dielectric, Lambertian, Material, Metal},
+    shapes::{SmoothTriangle, Triangle},
+    HittableList, Point3, Vec3,
+};
+
+/// Loads the faces of a Wavefront OBJ file as triangles, with the materials of the MTL files it
+/// refers to.
+///
+/// Only `v`, `vn`, `f`, `mtllib` and `usemtl` statements are used. Polygons with more than three
+/// vertices are split into a triangle fan. Faces giving a normal for every vertex
+/// (`f 1//1 2//2 3//3` or `f 1/1/1 2/2/2 3/3/3`) become `SmoothTriangle`s, the others are shaded
+/// flat. Texture coordinate indices are ignored.
+///
+/// Material libraries are looked up next to the OBJ file, see `load_mtl` for how their materials
+/// are translated. Faces before the first `usemtl`, or after one naming a material that none of
+/// the libraries define, get `mat`.
+pub fn load_obj(path: &str, mat: Arc<dyn Material>) -> io::Result<HittableList> {
+    let source = fs::read_to_string(path)?;
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+
+    let mut vertices: Vec<Point3> = vec![];
+    let mut normals: Vec<Vec3> = vec![];
+    let mut materials: HashMap<String, Arc<dyn Material>> = HashMap::new();
+    let mut current = mat.clone();
+    let mut triangles = HittableList::new();
+
+    for (line_idx, line) in source.lines().enumerate() {
+        let invalid = |msg: &str| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                format!("{path}:{}: {msg}", line_idx + 1),
+            )
+        };
+
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some(keyword @ ("v" | "vn")) => {
+                let coords = tokens
+                    .take(3)
+                    .map(|token| token.parse::<f64>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| invalid("invalid vertex coordinate"))?;
+                let [x, y, z] = coords[..] else {
+                    return Err(invalid("vertex needs three coordinates"));
+                };
+                if keyword == "v" {
+                    vertices.push(Point3::new(x, y, z));
+                } else {
+                    normals.push(Vec3::new(x, y, z));
+                }
+            }
+            Some("mtllib") => {
+                for library in tokens {
+                    materials.extend(load_mtl(&dir.join(library))?);
+                }
+            }
+            Some("usemtl") => {
+                current = tokens
+                    .next()
+                    .and_then(|name| materials.get(name))
+                    .unwrap_or(&mat)
+                    .clone();
+            }
+            Some("f") => {
+                let mut face = vec![];
+                let mut face_normals = vec![];
+                for token in tokens {
+                    let mut indices = token.split('/');
+                    let vertex = indices
+                        .next()
+                        .and_then(|index| resolve_index(index, vertices.len()))
+                        .ok_or_else(|| invalid("invalid vertex index"))?;
+                    face.push(vertices[vertex]);
+
+                    match indices.nth(1).filter(|index| !index.is_empty()) {
+                        Some(index) => {
+                            let normal = resolve_index(index, normals.len())
+                                .ok_or_else(|| invalid("invalid normal index"))?;
+                            face_normals.push(normals[normal]);
+                        }
+                        None => face_normals.clear(),
+                    }
+                }
+                if face.len() < 3 {
+                    return Err(invalid("face needs at least three vertices"));
+                }
+
+                // Only faces with a normal for every vertex are shaded smoothly.
+                let smooth = face_normals.len() == face.len();
+                for k in 1..face.len() - 1 {
+                    if smooth {
+                        triangles.add(Box::new(SmoothTriangle {
+                            a: face[0],
+                            b: face[k],
+                            c: face[k + 1],
+                            na: face_normals[0],
+                            nb: face_normals[k],
+                            nc: face_normals[k + 1],
+                            mat: current.clone(),
+                        }));
+                    } else {
+                        triangles.add(Box::new(Triangle {
+                            a: face[0],
+                            b: face[k],
+                            c: face[k + 1],
+                            mat: current.clone(),
+                        }));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(triangles)
+}
+
+/// Loads the materials of a Wavefront MTL file by name, translated to the closest of our
+/// materials:
+///
+/// - Transparent materials (`d` below 1 or `Tr` above 0) become `Dielectric`s with the
+///   refractive index `Ni`, 1.5 if not given.
+/// - Materials whose specular color `Ks` is brighter than their diffuse color `Kd` become
+///   `Metal`s of the specular color. The higher the specular exponent `Ns`, the less fuzz.
+/// - All others become `Lambertian`s of the diffuse color.
+///
+/// Texture maps and other statements are ignored.
+pub fn load_mtl(path: &Path) -> io::Result<HashMap<String, Arc<dyn Material>>> {
+    let source = fs::read_to_string(path)?;
+
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlParams)> = None;
+    for (line_idx, line) in source.lines().enumerate() {
+        let invalid = |msg: &str| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                format!("{}:{}: {msg}", path.display(), line_idx + 1),
+            )
+        };
+
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        if keyword == "newmtl" {
+            let name = tokens
+                .next()
+                .ok_or_else(|| invalid("material needs a name"))?;
+            if let Some((name, params)) = current.replace((name.to_string(), MtlParams::default()))
+            {
+                materials.insert(name, params.build());
+            }
+            continue;
+        }
+
+        let Some((_, params)) = &mut current else {
+            continue;
+        };
+        let values = tokens
+            .map(|token| token.parse::<f64>())
+            .collect::<Result<Vec<_>, _>>();
+        match (keyword, values.as_deref()) {
+            ("Kd" | "Ks", Ok(&[r, g, b, ..])) => {
+                let color = Color::new(r, g, b);
+                if keyword == "Kd" {
+                    params.diffuse = color;
+                } else {
+                    params.specular = color;
+                }
+            }
+            ("Ns", Ok(&[exponent, ..])) => params.specular_exponent = exponent,
+            ("Ni", Ok(&[index, ..])) => params.refraction_index = index,
+            ("d", Ok(&[dissolve, ..])) => params.opacity = dissolve,
+            ("Tr", Ok(&[transparency, ..])) => params.opacity = 1.0 - transparency,
+            ("Kd" | "Ks" | "Ns" | "Ni" | "d" | "Tr", _) => {
+                return Err(invalid(&format!("invalid {keyword} value")))
+            }
+            _ => {}
+        }
+    }
+    if let Some((name, params)) = current {
+        materials.insert(name, params.build());
+    }
+
+    Ok(materials)
+}
+
+/// The statements of an MTL material that `load_mtl` reads.
+struct MtlParams {
+    diffuse: Color,
+    specular: Color,
+    specular_exponent: f64,
+    refraction_index: f64,
+    opacity: f64,
+}
+
+impl Default for MtlParams {
+    fn default() -> Self {
+        Self {
+            diffuse: Color::splat(0.8),
+            specular: Color::ZERO,
+            specular_exponent: 0.0,
+            refraction_index: 1.5,
+            opacity: 1.0,
+        }
+    }
+}
+
+impl MtlParams {
+    fn build(&self) -> Arc<dyn Material> {
+        if self.opacity < 1.0 {
+            return Arc::new(Dielectric::new(self.refraction_index));
+        }
+
+        if self.specular.max_element() > self.diffuse.max_element() {
+            // The width of a Phong lobe with this exponent, from 1 for 0 to about 0.05 for 1000.
+            let fuzz = (2.0 / (self.specular_exponent.max(0.0) + 2.0)).sqrt();
+            return Arc::new(Metal {
+                albedo: self.specular,
+                fuzz,
+            });
+        }
+
+        Arc::new(Lambertian::new(self.diffuse))
+    }
+}
+
+/// Converts a face's reference like `3` or `-1` to a vertex or normal into an index into the
+/// `count` of them defined so far. Positive indices are 1-based, negative ones count back from
+/// the end.
+fn resolve_index(token: &str, count: usize) -> Option<usize> {
+    let index: i64 = token.parse().ok()?;
+
+    let resolved = match index {
+        1.. => index - 1,
+        ..=-1 => count as i64 + index,
+        0 => return None,
+    };
+
+    (0..count as i64)
+        .contains(&resolved)
+        .then_some(resolved as usize)
+}
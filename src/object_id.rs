@@ -0,0 +1,43 @@
+use rand::RngCore;
+
+use crate::{aabb::Aabb, HitRecord, Hittable, Interval, Point3, Ray, Vec3};
+
+/// Tags the hits of an object with `id`, for the ID pass of `Camera::render_ids`, e.g. to mask
+/// the object out when compositing. IDs of objects nested in it take precedence, so a group can
+/// have an ID for the objects in it that have none of their own.
+pub struct Identified {
+    /// Should be 1 or more, since 0 marks pixels without an ID in the ID pass
+    pub id: u16,
+    pub object: Box<dyn Hittable>,
+}
+
+impl Hittable for Identified {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let mut rec = self.object.hit(r, ray_t)?;
+        rec.object_id = rec.object_id.or(Some(self.id));
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.object.bounding_box()
+    }
+
+    fn bvh_node_count(&self) -> u64 {
+        self.object.bvh_node_count()
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3, shadow_bias: f64) -> f64 {
+        self.object.pdf_value(origin, direction, shadow_bias)
+    }
+
+    fn random(&self, origin: Point3, rng: &mut dyn RngCore) -> Vec3 {
+        self.object.random(origin, rng)
+    }
+
+    fn sample_surface(&self, time: f64, rng: &mut dyn RngCore) -> Option<(HitRecord, f64)> {
+        let (mut rec, pdf) = self.object.sample_surface(time, rng)?;
+        rec.object_id = rec.object_id.or(Some(self.id));
+        Some((rec, pdf))
+    }
+}
@@ -0,0 +1,150 @@
+use std::f64::consts::PI;
+
+use rand::{Rng, RngCore};
+
+use crate::{random_vec3_unit, vec3, Hittable, Point3, Vec3};
+
+/// A probability density over directions, used to importance sample scattered rays.
+pub trait Pdf {
+    /// Returns the density of `direction`, which doesn't need to be normalized.
+    fn value(&self, direction: Vec3) -> f64;
+
+    /// Returns a random direction distributed according to the density.
+    fn generate(&self, rng: &mut dyn RngCore) -> Vec3;
+}
+
+/// An orthonormal basis, used to turn directions sampled around the z axis into directions
+/// around an arbitrary axis.
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    /// Builds a basis whose `w` axis points along `n`.
+    pub fn new(n: Vec3) -> Self {
+        let w = n.normalize();
+        let a = if w.x.abs() > 0.9 {
+            vec3(0.0, 1.0, 0.0)
+        } else {
+            vec3(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(a).normalize();
+        let u = w.cross(v);
+
+        Self { u, v, w }
+    }
+
+    /// Transforms `v` from basis coordinates to world coordinates.
+    pub fn transform(&self, v: Vec3) -> Vec3 {
+        v.x * self.u + v.y * self.v + v.z * self.w
+    }
+}
+
+/// Uniform density over every direction.
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, _direction: Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate(&self, rng: &mut dyn RngCore) -> Vec3 {
+        random_vec3_unit(rng)
+    }
+}
+
+/// Density proportional to the cosine of the angle to a surface normal, which matches the
+/// scattering of a Lambertian surface exactly.
+pub struct CosinePdf {
+    uvw: Onb,
+}
+
+impl CosinePdf {
+    pub fn new(normal: Vec3) -> Self {
+        Self {
+            uvw: Onb::new(normal),
+        }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        let cosine_theta = direction.normalize().dot(self.uvw.w);
+
+        f64::max(0.0, cosine_theta / PI)
+    }
+
+    fn generate(&self, rng: &mut dyn RngCore) -> Vec3 {
+        self.uvw.transform(random_cosine_direction(rng))
+    }
+}
+
+/// Density of the directions from `origin` towards points on `objects`, e.g. the lights of a
+/// scene, counting only points past `shadow_bias` as `Hittable::pdf_value` does.
+pub struct HittablePdf<'a> {
+    objects: &'a dyn Hittable,
+    origin: Point3,
+    shadow_bias: f64,
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn new(objects: &'a dyn Hittable, origin: Point3, shadow_bias: f64) -> Self {
+        Self {
+            objects,
+            origin,
+            shadow_bias,
+        }
+    }
+}
+
+impl Pdf for HittablePdf<'_> {
+    fn value(&self, direction: Vec3) -> f64 {
+        self.objects
+            .pdf_value(self.origin, direction, self.shadow_bias)
+    }
+
+    fn generate(&self, rng: &mut dyn RngCore) -> Vec3 {
+        self.objects.random(self.origin, rng)
+    }
+}
+
+/// An even blend of two densities.
+pub struct MixturePdf<'a> {
+    p: [&'a dyn Pdf; 2],
+}
+
+impl<'a> MixturePdf<'a> {
+    pub fn new(p0: &'a dyn Pdf, p1: &'a dyn Pdf) -> Self {
+        Self { p: [p0, p1] }
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: Vec3) -> f64 {
+        0.5 * self.p[0].value(direction) + 0.5 * self.p[1].value(direction)
+    }
+
+    fn generate(&self, rng: &mut dyn RngCore) -> Vec3 {
+        if rng.gen::<f64>() < 0.5 {
+            self.p[0].generate(rng)
+        } else {
+            self.p[1].generate(rng)
+        }
+    }
+}
+
+/// Returns a random unit vector around the z axis, with a density proportional to its z
+/// component.
+pub fn random_cosine_direction(rng: &mut dyn RngCore) -> Vec3 {
+    let r1: f64 = rng.gen();
+    let r2: f64 = rng.gen();
+
+    let phi = 2.0 * PI * r1;
+    let x = phi.cos() * r2.sqrt();
+    let y = phi.sin() * r2.sqrt();
+    let z = (1.0 - r2).sqrt();
+
+    vec3(x, y, z)
+}
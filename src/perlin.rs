@@ -0,0 +1,104 @@
+use rand::{seq::SliceRandom, RngCore};
+
+use crate::{random_vec3_range, Point3, Vec3};
+
+const POINT_COUNT: usize = 256;
+
+/// Perlin gradient noise.
+pub struct Perlin {
+    randvec: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    /// Builds the gradient and permutation tables from `rng`, so a seeded generator always
+    /// produces the same noise.
+    pub fn new(rng: &mut dyn RngCore) -> Self {
+        let randvec = (0..POINT_COUNT)
+            .map(|_| random_vec3_range(rng, (-1.0)..1.0).normalize())
+            .collect();
+
+        Self {
+            randvec,
+            perm_x: Self::generate_perm(rng),
+            perm_y: Self::generate_perm(rng),
+            perm_z: Self::generate_perm(rng),
+        }
+    }
+
+    /// Returns smoothly varying noise in [-1,1] at `p`.
+    pub fn noise(&self, p: &Point3) -> f64 {
+        let u = p.x - p.x.floor();
+        let v = p.y - p.y.floor();
+        let w = p.z - p.z.floor();
+
+        let i = p.x.floor() as i64;
+        let j = p.y.floor() as i64;
+        let k = p.z.floor() as i64;
+
+        let mut c = [[[Vec3::ZERO; 2]; 2]; 2];
+        for (di, plane) in c.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    let idx = self.perm_x[wrap(i + di as i64)]
+                        ^ self.perm_y[wrap(j + dj as i64)]
+                        ^ self.perm_z[wrap(k + dk as i64)];
+                    *corner = self.randvec[idx];
+                }
+            }
+        }
+
+        Self::perlin_interp(&c, u, v, w)
+    }
+
+    /// Sums `depth` octaves of noise, each at twice the frequency and half the weight of the
+    /// previous one.
+    pub fn turb(&self, p: &Point3, depth: i32) -> f64 {
+        let mut accum = 0.0;
+        let mut temp_p = *p;
+        let mut weight = 1.0;
+
+        for _ in 0..depth {
+            accum += weight * self.noise(&temp_p);
+            weight *= 0.5;
+            temp_p *= 2.0;
+        }
+
+        accum.abs()
+    }
+
+    fn generate_perm(rng: &mut dyn RngCore) -> Vec<usize> {
+        let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+        p.shuffle(rng);
+        p
+    }
+
+    fn perlin_interp(c: &[[[Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
+        // Hermite smoothing avoids the grid artifacts of plain trilinear interpolation.
+        let uu = u * u * (3.0 - 2.0 * u);
+        let vv = v * v * (3.0 - 2.0 * v);
+        let ww = w * w * (3.0 - 2.0 * w);
+
+        let mut accum = 0.0;
+        for (i, plane) in c.iter().enumerate() {
+            for (j, row) in plane.iter().enumerate() {
+                for (k, corner) in row.iter().enumerate() {
+                    let (fi, fj, fk) = (i as f64, j as f64, k as f64);
+                    let weight_v = Vec3::new(u - fi, v - fj, w - fk);
+                    accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * corner.dot(weight_v);
+                }
+            }
+        }
+
+        accum
+    }
+}
+
+fn wrap(i: i64) -> usize {
+    (i & (POINT_COUNT as i64 - 1)) as usize
+}
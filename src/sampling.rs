@@ -0,0 +1,175 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Side length of the tiled blue-noise mask.
+const MASK_SIZE: usize = 32;
+
+/// Standard deviation of the gaussian energy filter used by void-and-cluster.
+const SIGMA: f64 = 1.5;
+
+/// Returns the `n`th point of the R2 low-discrepancy sequence in the [0,1)x[0,1) square.
+pub fn r2(n: usize) -> (f64, f64) {
+    // Inverse powers of the plastic number, the 2D analogue of the golden ratio.
+    const A1: f64 = 0.754_877_666_246_692_8;
+    const A2: f64 = 0.569_840_290_998_053_3;
+
+    let n = n as f64;
+    ((0.5 + A1 * n).fract(), (0.5 + A2 * n).fract())
+}
+
+/// A tileable two-channel blue-noise mask, used to decorrelate the sample patterns of
+/// neighboring pixels (Cranley–Patterson rotation).
+#[derive(Clone)]
+pub struct BlueNoiseMask {
+    values: Vec<(f64, f64)>,
+}
+
+impl BlueNoiseMask {
+    /// Generates the mask with the void-and-cluster method. The same seed always produces the
+    /// same mask.
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let xs = void_and_cluster(&mut rng);
+        let ys = void_and_cluster(&mut rng);
+
+        Self {
+            values: xs.into_iter().zip(ys).collect(),
+        }
+    }
+
+    /// Returns the offset in [0,1)x[0,1) assigned to the pixel i, j.
+    pub fn offset(&self, i: i32, j: i32) -> (f64, f64) {
+        let x = i.rem_euclid(MASK_SIZE as i32) as usize;
+        let y = j.rem_euclid(MASK_SIZE as i32) as usize;
+
+        self.values[y * MASK_SIZE + x]
+    }
+}
+
+/// Produces a single blue-noise channel of `MASK_SIZE * MASK_SIZE` values in [0,1).
+fn void_and_cluster(rng: &mut impl Rng) -> Vec<f64> {
+    let n = MASK_SIZE * MASK_SIZE;
+    let kernel = energy_kernel();
+
+    // Start from a sparse random pattern and relax it until the tightest cluster is also the
+    // largest void.
+    let mut pattern = Pattern::new(&kernel);
+    while pattern.count < n / 10 {
+        let idx = rng.gen_range(0..n);
+        if !pattern.bits[idx] {
+            pattern.toggle(idx);
+        }
+    }
+    loop {
+        let cluster = pattern.tightest_cluster();
+        pattern.toggle(cluster);
+        let void = pattern.largest_void();
+        if void == cluster {
+            pattern.toggle(void);
+            break;
+        }
+        pattern.toggle(void);
+    }
+
+    let mut ranks = vec![0; n];
+    let initial = pattern.clone();
+
+    // Rank the initial points by repeatedly removing the tightest cluster.
+    let mut rank = pattern.count;
+    while pattern.count > 0 {
+        rank -= 1;
+        let cluster = pattern.tightest_cluster();
+        pattern.toggle(cluster);
+        ranks[cluster] = rank;
+    }
+
+    // Rank the remaining points by repeatedly filling the largest void.
+    let mut pattern = initial;
+    while pattern.count < n {
+        let void = pattern.largest_void();
+        ranks[void] = pattern.count;
+        pattern.toggle(void);
+    }
+
+    ranks
+        .into_iter()
+        .map(|rank| (rank as f64 + 0.5) / n as f64)
+        .collect()
+}
+
+/// Returns the toroidal gaussian weights indexed by the wrapped `dy * MASK_SIZE + dx` offset.
+fn energy_kernel() -> Vec<f64> {
+    let mut kernel = vec![0.0; MASK_SIZE * MASK_SIZE];
+
+    for dy in 0..MASK_SIZE {
+        for dx in 0..MASK_SIZE {
+            let wrap = |d: usize| d.min(MASK_SIZE - d) as f64;
+            let dist_squared = wrap(dx).powi(2) + wrap(dy).powi(2);
+            kernel[dy * MASK_SIZE + dx] = (-dist_squared / (2.0 * SIGMA * SIGMA)).exp();
+        }
+    }
+
+    kernel
+}
+
+#[derive(Clone)]
+struct Pattern<'a> {
+    kernel: &'a [f64],
+    bits: Vec<bool>,
+    energy: Vec<f64>,
+    count: usize,
+}
+
+impl<'a> Pattern<'a> {
+    fn new(kernel: &'a [f64]) -> Self {
+        Self {
+            kernel,
+            bits: vec![false; kernel.len()],
+            energy: vec![0.0; kernel.len()],
+            count: 0,
+        }
+    }
+
+    fn toggle(&mut self, idx: usize) {
+        let sign = if self.bits[idx] { -1.0 } else { 1.0 };
+        self.bits[idx] = !self.bits[idx];
+        self.count = if self.bits[idx] {
+            self.count + 1
+        } else {
+            self.count - 1
+        };
+
+        let (x0, y0) = (idx % MASK_SIZE, idx / MASK_SIZE);
+        for y in 0..MASK_SIZE {
+            for x in 0..MASK_SIZE {
+                let dx = (x + MASK_SIZE - x0) % MASK_SIZE;
+                let dy = (y + MASK_SIZE - y0) % MASK_SIZE;
+                self.energy[y * MASK_SIZE + x] += sign * self.kernel[dy * MASK_SIZE + dx];
+            }
+        }
+    }
+
+    /// The set point with the highest energy.
+    fn tightest_cluster(&self) -> usize {
+        self.extreme(true, |a, b| a > b)
+    }
+
+    /// The unset point with the lowest energy.
+    fn largest_void(&self) -> usize {
+        self.extreme(false, |a, b| a < b)
+    }
+
+    fn extreme(&self, bit: bool, better: impl Fn(f64, f64) -> bool) -> usize {
+        let mut best = None;
+        for (idx, &energy) in self.energy.iter().enumerate() {
+            if self.bits[idx] != bit {
+                continue;
+            }
+            match best {
+                Some((_, best_energy)) if !better(energy, best_energy) => {}
+                _ => best = Some((idx, energy)),
+            }
+        }
+
+        best.map(|(idx, _)| idx).unwrap_or(0)
+    }
+}
@@ -0,0 +1,364 @@
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+    sync::Arc,
+};
+
+use image::ImageResult;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::{
+    bvh::BvhNode,
+    camera::{Camera, CameraParams, DEFAULT_SHADOW_BIAS},
+    color::Color,
+    material::{
+        Dielectric, DiffuseLight, GlowingLambertian, Lambertian, Material, Metal, OrenNayar,
+    },
+    object_id::Identified,
+    stats::RenderStats,
+    HittableList, Point3, Sphere, Vec3,
+};
+
+/// Loads a scene description from a JSON file: the camera and a list of spheres.
+///
+/// ```json
+/// {
+///   "camera": { "image_width": 400, "lookfrom": [13, 2, 3], "lookat": [0, 0, 0] },
+///   "spheres": [
+///     {
+///       "center": [0, 1, 0],
+///       "radius": 1,
+///       "material": { "type": "metal", "albedo": [0.7, 0.6, 0.5], "fuzz": 0.1 }
+///     }
+///   ]
+/// }
+/// ```
+///
+/// Camera fields that are left out keep the defaults of the random spheres scene. Camera options
+/// not covered by the file, like the tone map, keep their defaults too and can be changed on the
+/// returned parameters.
+pub fn load_scene(path: &str) -> io::Result<(HittableList, CameraParams)> {
+    let scene = read_scene_file(path)?;
+    let camera = camera_config(scene.camera.clone())?;
+
+    Ok((build_world(scene), camera.into()))
+}
+
+/// Loads a scene file like `load_scene`, along with its list of named cameras:
+///
+/// ```json
+/// {
+///   "camera": { "image_width": 400, "lookat": [0, 1, 0] },
+///   "cameras": [
+///     { "name": "front", "lookfrom": [0, 2, 13] },
+///     { "name": "side", "lookfrom": [13, 2, 0], "image_width": 800, "samples_per_pixel": 500 }
+///   ],
+///   "spheres": []
+/// }
+/// ```
+///
+/// Each camera takes its fields from `camera` and overrides any of them, like the resolution or
+/// the number of samples. A file without `cameras` has a single one named `camera`. Names must be
+/// unique, non-empty and without path separators, as they name the rendered files.
+pub fn load_scene_cameras(path: &str) -> io::Result<(HittableList, SceneCameras)> {
+    let mut scene = read_scene_file(path)?;
+
+    let mut cameras = vec![];
+    let mut names = HashSet::new();
+    for mut overrides in std::mem::take(&mut scene.cameras) {
+        let name = match overrides.remove("name") {
+            Some(Value::String(name)) => name,
+            _ => return Err(invalid_data("every camera needs a \"name\" string")),
+        };
+        if name.is_empty() || name.contains(['/', '\\']) || name == "." || name == ".." {
+            return Err(invalid_data(format!("invalid camera name {name:?}")));
+        }
+        if !names.insert(name.clone()) {
+            return Err(invalid_data(format!("duplicate camera name {name:?}")));
+        }
+
+        let mut fields = scene.camera.clone();
+        fields.extend(overrides);
+        cameras.push(NamedCamera {
+            name,
+            params: camera_config(fields)?.into(),
+        });
+    }
+    if cameras.is_empty() {
+        cameras.push(NamedCamera {
+            name: "camera".to_string(),
+            params: camera_config(scene.camera.clone())?.into(),
+        });
+    }
+
+    Ok((build_world(scene), SceneCameras { cameras }))
+}
+
+/// A camera of a scene file, see `load_scene_cameras`.
+#[derive(Clone)]
+pub struct NamedCamera {
+    pub name: String,
+    pub params: CameraParams,
+}
+
+/// The cameras of a scene file, in the order they're listed.
+#[derive(Clone)]
+pub struct SceneCameras {
+    pub cameras: Vec<NamedCamera>,
+}
+
+impl SceneCameras {
+    /// Renders the world through every camera to `<name>.png` in `out_dir`, which is created if
+    /// needed, and returns the statistics of every render. The world is put in a single BVH
+    /// shared by all of them.
+    pub fn render_all_cameras(
+        &self,
+        world: HittableList,
+        out_dir: impl AsRef<Path>,
+    ) -> ImageResult<Vec<RenderStats>> {
+        let out_dir = out_dir.as_ref();
+        fs::create_dir_all(out_dir)?;
+
+        let world = BvhNode::new(world);
+        self.cameras
+            .iter()
+            .map(|camera| {
+                let mut cam = Camera::new(camera.params.clone());
+                let (image, stats) = cam.render_image(&world);
+                cam.save_image(&image, out_dir.join(format!("{}.png", camera.name)))?;
+
+                Ok(stats)
+            })
+            .collect()
+    }
+}
+
+fn read_scene_file(path: &str) -> io::Result<SceneFile> {
+    let source = fs::read_to_string(path)?;
+    serde_json::from_str(&source).map_err(invalid_data)
+}
+
+fn camera_config(fields: Map<String, Value>) -> io::Result<CameraConfig> {
+    serde_json::from_value(Value::Object(fields)).map_err(invalid_data)
+}
+
+fn invalid_data(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, err)
+}
+
+fn build_world(scene: SceneFile) -> HittableList {
+    let mut world = HittableList::new();
+    for sphere in scene.spheres {
+        let object = Box::new(Sphere::new(
+            sphere.center,
+            sphere.radius,
+            sphere.material.build(),
+        ));
+        match sphere.id {
+            Some(id) => world.add(Box::new(Identified { id, object })),
+            None => world.add(object),
+        };
+    }
+
+    world
+}
+
+/// The camera fields are kept as JSON until the named cameras are merged over them, and are
+/// checked against `CameraConfig` then.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    #[serde(default)]
+    camera: Map<String, Value>,
+    #[serde(default)]
+    cameras: Vec<Map<String, Value>>,
+    #[serde(default)]
+    spheres: Vec<SphereConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct CameraConfig {
+    aspect_ratio: f64,
+    image_width: i32,
+    samples_per_pixel: i32,
+    max_depth: i32,
+    vfov: f64,
+    lookfrom: Point3,
+    lookat: Point3,
+    vup: Vec3,
+    defocus_angle: f64,
+    focus_dist: f64,
+    focus_on: Option<Point3>,
+    exposure: f64,
+    shadow_bias: f64,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self {
+            aspect_ratio: 16.0 / 9.0,
+            image_width: 600,
+            samples_per_pixel: 100,
+            max_depth: 25,
+            vfov: 20.0,
+            lookfrom: Point3::new(13.0, 2.0, 3.0),
+            lookat: Point3::ZERO,
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            defocus_angle: 0.6,
+            focus_dist: 10.0,
+            focus_on: None,
+            exposure: 1.0,
+            shadow_bias: DEFAULT_SHADOW_BIAS,
+        }
+    }
+}
+
+impl From<CameraConfig> for CameraParams {
+    fn from(camera: CameraConfig) -> Self {
+        Self {
+            aspect_ratio: camera.aspect_ratio,
+            image_width: camera.image_width,
+            samples_per_pixel: camera.samples_per_pixel,
+            max_depth: camera.max_depth,
+            vfov: camera.vfov,
+            lookfrom: camera.lookfrom,
+            lookat: camera.lookat,
+            vup: camera.vup,
+            defocus_angle: camera.defocus_angle,
+            focus_dist: camera.focus_dist,
+            focus_on: camera.focus_on,
+            exposure: camera.exposure,
+            shadow_bias: camera.shadow_bias,
+            ..CameraParams::default()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SphereConfig {
+    center: Point3,
+    radius: f64,
+    material: MaterialConfig,
+    /// See `Identified::id`
+    id: Option<u16>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialConfig {
+    Lambertian {
+        albedo: Color,
+    },
+    Metal {
+        albedo: Color,
+        fuzz: f64,
+    },
+    Dielectric {
+        refraction_index: f64,
+        /// Cauchy coefficient, see `Dielectric::dispersion`
+        #[serde(default)]
+        dispersion: f64,
+    },
+    DiffuseLight {
+        emit: Color,
+    },
+    GlowingLambertian {
+        albedo: Color,
+        emit: Color,
+    },
+    OrenNayar {
+        albedo: Color,
+        roughness: f64,
+    },
+}
+
+impl MaterialConfig {
+    fn build(self) -> Arc<dyn Material> {
+        match self {
+            MaterialConfig::Lambertian { albedo } => Arc::new(Lambertian::new(albedo)),
+            MaterialConfig::Metal { albedo, fuzz } => Arc::new(Metal { albedo, fuzz }),
+            MaterialConfig::Dielectric {
+                refraction_index,
+                dispersion,
+            } => Arc::new(Dielectric::new(refraction_index).with_dispersion(dispersion)),
+            MaterialConfig::DiffuseLight { emit } => Arc::new(DiffuseLight { emit }),
+            MaterialConfig::GlowingLambertian { albedo, emit } => {
+                Arc::new(GlowingLambertian::new(albedo, emit))
+            }
+            MaterialConfig::OrenNayar { albedo, roughness } => {
+                Arc::new(OrenNayar::new(albedo, roughness))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str, source: &str) -> io::Result<(HittableList, SceneCameras)> {
+        let path = std::env::temp_dir().join(format!("scene_test_{name}.json"));
+        fs::write(&path, source).unwrap();
+        let result = load_scene_cameras(path.to_str().unwrap());
+        fs::remove_file(path).unwrap();
+
+        result
+    }
+
+    #[test]
+    fn named_cameras_override_the_shared_camera() {
+        let (_, cameras) = load(
+            "overrides",
+            r#"{
+                "camera": { "image_width": 200, "samples_per_pixel": 10, "lookat": [0, 1, 0] },
+                "cameras": [
+                    { "name": "front", "lookfrom": [0, 2, 13] },
+                    { "name": "side", "image_width": 80, "samples_per_pixel": 3 }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let [front, side] = &cameras.cameras[..] else {
+            panic!("expected two cameras");
+        };
+
+        assert_eq!(front.name, "front");
+        assert_eq!(front.params.lookfrom, Point3::new(0.0, 2.0, 13.0));
+        assert_eq!(front.params.image_width, 200);
+        assert_eq!(side.name, "side");
+        assert_eq!(side.params.lookfrom, CameraConfig::default().lookfrom);
+        assert_eq!(side.params.lookat, Point3::new(0.0, 1.0, 0.0));
+        assert_eq!(
+            (side.params.image_width, side.params.samples_per_pixel),
+            (80, 3)
+        );
+    }
+
+    #[test]
+    fn files_without_cameras_have_one() {
+        let (_, cameras) = load("single", r#"{ "camera": { "image_width": 50 } }"#).unwrap();
+        assert_eq!(cameras.cameras.len(), 1);
+        assert_eq!(cameras.cameras[0].name, "camera");
+        assert_eq!(cameras.cameras[0].params.image_width, 50);
+    }
+
+    #[test]
+    fn camera_names_must_make_file_names() {
+        for cameras in [
+            r#"[{ "lookfrom": [1, 2, 3] }]"#,
+            r#"[{ "name": "" }]"#,
+            r#"[{ "name": "../up" }]"#,
+            r#"[{ "name": "a" }, { "name": "a" }]"#,
+            r#"[{ "name": "a", "image_widht": 10 }]"#,
+        ] {
+            let source = format!(r#"{{ "cameras": {cameras} }}"#);
+            let err = load("invalid", &source).err().expect(cameras);
+            assert_eq!(err.kind(), ErrorKind::InvalidData, "{cameras}");
+        }
+    }
+}
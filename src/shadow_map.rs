@@ -0,0 +1,61 @@
+use crate::{Hittable, Interval, Point3, Ray, RayKind, Vec3};
+
+/// An orthographic view from a light, e.g. a directional light shining from `lookfrom` towards
+/// `lookat`.
+pub struct LightView {
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    pub vup: Vec3,
+    /// Width of the view volume in world units
+    pub width: f64,
+    /// Height of the view volume in world units
+    pub height: f64,
+    /// Distance from `lookfrom` at which depth 0.0 starts. Nearer geometry is clipped.
+    pub near: f64,
+    /// Distance from `lookfrom` that maps to depth 1.0. Farther geometry is clipped.
+    pub far: f64,
+}
+
+/// Renders an orthographic depth map of `world` as seen from `light_view`.
+///
+/// The returned buffer is row-major, starting from the top-left pixel, with a length of
+/// `width * height` of `resolution`. Each value is the linear distance to the first hit along
+/// the light's forward axis, mapped so that `near` is 0.0 and `far` is 1.0. Pixels that don't
+/// hit anything between `near` and `far` are 1.0.
+pub fn render_shadow_map(
+    world: &impl Hittable,
+    light_view: &LightView,
+    resolution: (i32, i32),
+) -> Vec<f64> {
+    let (width, height) = resolution;
+
+    // Calculate the u,v,w unit basis vectors for the light's coordinate frame.
+    let w = (light_view.lookfrom - light_view.lookat).normalize();
+    let u = light_view.vup.cross(w).normalize();
+    let v = w.cross(u);
+
+    let pixel_delta_u = light_view.width * u / width as f64;
+    let pixel_delta_v = light_view.height * -v / height as f64;
+    let upper_left =
+        light_view.lookfrom - light_view.width * u / 2.0 - light_view.height * -v / 2.0
+            + 0.5 * (pixel_delta_u + pixel_delta_v);
+
+    let ray_t = Interval::new(light_view.near, light_view.far);
+    let depth_range = light_view.far - light_view.near;
+
+    let mut depths = Vec::with_capacity((width * height) as usize);
+    for j in 0..height {
+        for i in 0..width {
+            let origin = upper_left + i as f64 * pixel_delta_u + j as f64 * pixel_delta_v;
+            let r = Ray::new(origin, -w).with_kind(RayKind::Shadow);
+
+            let depth = match world.hit(&r, ray_t) {
+                Some(rec) => (rec.t - light_view.near) / depth_range,
+                None => 1.0,
+            };
+            depths.push(depth);
+        }
+    }
+
+    depths
+}
@@ -0,0 +1,800 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use rand::{Rng, RngCore};
+
+use crate::{
+    aabb::Aabb,
+    color::Color,
+    material::{Lambertian, Material},
+    pdf::Onb,
+    random_vec3_on_unit_disc, vec3, HitRecord, Hittable, HittableList, Interval, Point3, Ray,
+    Sphere, Vec3,
+};
+
+/// A parallelogram with corner `q` and sides `u` and `v`.
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    mat: Arc<dyn Material>,
+
+    normal: Vec3,
+    /// Plane constant, `normal.dot(p) == d` for every point `p` on the plane
+    d: f64,
+    /// Used for projecting hit points onto the `u`, `v` basis
+    w: Vec3,
+    area: f64,
+}
+
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, mat: Arc<dyn Material>) -> Self {
+        let n = u.cross(v);
+        // A degenerate quad gets a zero normal, which every ray is parallel to.
+        let normal = n.normalize_or_zero();
+        let w = if normal == Vec3::ZERO {
+            Vec3::ZERO
+        } else {
+            n / n.dot(n)
+        };
+
+        Self {
+            q,
+            u,
+            v,
+            mat,
+            normal,
+            d: normal.dot(q),
+            w,
+            area: n.length(),
+        }
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let denom = self.normal.dot(r.direction());
+
+        // No hit if the ray is parallel to the plane.
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+
+        // No hit if the hit point parameter t is outside the ray interval.
+        let t = (self.d - self.normal.dot(r.origin)) / denom;
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+
+        // Determine if the hit point lies within the planar shape using its plane coordinates.
+        let intersection = r.at(t);
+        let planar_hitpt_vector = intersection - self.q;
+        let alpha = self.w.dot(planar_hitpt_vector.cross(self.v));
+        let beta = self.w.dot(self.u.cross(planar_hitpt_vector));
+
+        let unit_interval = Interval::new(0.0, 1.0);
+        if !(unit_interval.contains(alpha) && unit_interval.contains(beta)) {
+            return None;
+        }
+
+        let mut rec = HitRecord::new(intersection, t, self.mat.clone(), r, self.normal);
+        (rec.u, rec.v) = (alpha, beta);
+        rec.tangent = self.u.normalize();
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // Compute the bounding box of all four vertices.
+        let bbox_diagonal1 = Aabb::from_points(self.q, self.q + self.u + self.v);
+        let bbox_diagonal2 = Aabb::from_points(self.q + self.u, self.q + self.v);
+
+        bbox_diagonal1.union(&bbox_diagonal2).pad()
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3, shadow_bias: f64) -> f64 {
+        let Some(rec) = self.hit(
+            &Ray::new(origin, direction),
+            Interval::new(shadow_bias, f64::INFINITY),
+        ) else {
+            return 0.0;
+        };
+
+        // Convert the uniform density over the quad's area into a density over solid angle.
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = (direction.dot(rec.normal) / direction.length()).abs();
+
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random(&self, origin: Point3, rng: &mut dyn RngCore) -> Vec3 {
+        let p = self.q + rng.gen::<f64>() * self.u + rng.gen::<f64>() * self.v;
+
+        p - origin
+    }
+
+    fn sample_surface(&self, time: f64, rng: &mut dyn RngCore) -> Option<(HitRecord, f64)> {
+        if self.area <= 0.0 {
+            return None;
+        }
+
+        let (alpha, beta) = (rng.gen::<f64>(), rng.gen::<f64>());
+        let p = self.q + alpha * self.u + beta * self.v;
+        let r = Ray::new(p + self.normal, -self.normal).with_time(time);
+        let mut rec = HitRecord::new(p, 0.0, self.mat.clone(), &r, self.normal);
+        (rec.u, rec.v) = (alpha, beta);
+        rec.tangent = self.u.normalize();
+
+        Some((rec, 1.0 / self.area))
+    }
+}
+
+/// Returns the six sides of the axis-aligned box with opposite corners `a` and `b`, with their
+/// normals facing out of the box.
+pub fn make_box(a: Point3, b: Point3, mat: Arc<dyn Material>) -> HittableList {
+    let mut sides = HittableList::new();
+
+    let min = a.min(b);
+    let max = a.max(b);
+
+    let dx = vec3(max.x - min.x, 0.0, 0.0);
+    let dy = vec3(0.0, max.y - min.y, 0.0);
+    let dz = vec3(0.0, 0.0, max.z - min.z);
+
+    let mut add = |q: Point3, u: Vec3, v: Vec3| {
+        sides.add(Box::new(Quad::new(q, u, v, mat.clone())));
+    };
+    add(vec3(min.x, min.y, max.z), dx, dy); // front
+    add(vec3(max.x, min.y, max.z), -dz, dy); // right
+    add(vec3(max.x, min.y, min.z), -dx, dy); // back
+    add(vec3(min.x, min.y, min.z), dz, dy); // left
+    add(vec3(min.x, max.y, max.z), dx, -dz); // top
+    add(vec3(min.x, min.y, min.z), dx, dz); // bottom
+
+    sides
+}
+
+/// Stands in for `object` with its bounding box, shaded with a flat gray, while `enabled` is
+/// set. Boxes are much cheaper to hit than e.g. a mesh of thousands of triangles, which makes
+/// for quick previews of a scene's layout.
+///
+/// Objects without a finite bounding box, like an `InfinitePlane`, have no box to stand in for
+/// them and are always shown as they are.
+pub struct BoundingProxy {
+    pub object: Box<dyn Hittable>,
+    pub enabled: bool,
+    /// The sides of the bounding box, if it's finite
+    sides: Option<HittableList>,
+}
+
+impl BoundingProxy {
+    pub fn new(object: Box<dyn Hittable>) -> Self {
+        let bbox = object.bounding_box();
+        let mat = Arc::new(Lambertian::new(Color::splat(0.5)));
+
+        Self {
+            sides: bbox
+                .is_valid()
+                .then(|| make_box(bbox.min(), bbox.max(), mat)),
+            object,
+            enabled: true,
+        }
+    }
+
+    /// The box while it stands in for the object, otherwise the object itself.
+    fn shown(&self) -> &dyn Hittable {
+        match &self.sides {
+            Some(sides) if self.enabled => sides,
+            _ => self.object.as_ref(),
+        }
+    }
+}
+
+impl Hittable for BoundingProxy {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        self.shown().hit(r, ray_t)
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3, shadow_bias: f64) -> f64 {
+        self.shown().pdf_value(origin, direction, shadow_bias)
+    }
+
+    fn random(&self, origin: Point3, rng: &mut dyn RngCore) -> Vec3 {
+        self.shown().random(origin, rng)
+    }
+
+    fn sample_surface(&self, time: f64, rng: &mut dyn RngCore) -> Option<(HitRecord, f64)> {
+        self.shown().sample_surface(time, rng)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.object.bounding_box()
+    }
+
+    fn bvh_node_count(&self) -> u64 {
+        self.object.bvh_node_count()
+    }
+}
+
+pub struct Triangle {
+    pub a: Point3,
+    pub b: Point3,
+    pub c: Point3,
+    pub mat: Arc<dyn Material>,
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let (t, u, v) = hit_triangle(self.a, self.b, self.c, r, ray_t)?;
+
+        let outward_normal = (self.b - self.a).cross(self.c - self.a).normalize();
+        let mut rec = HitRecord::new(r.at(t), t, self.mat.clone(), r, outward_normal);
+        (rec.u, rec.v) = (u, v);
+        rec.tangent = (self.b - self.a).normalize();
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        triangle_bounding_box(self.a, self.b, self.c)
+    }
+}
+
+/// A triangle shaded as part of a curved surface: its normal blends from the vertex normals
+/// `na`, `nb` and `nc` across the face, which hides the edges between neighboring triangles.
+pub struct SmoothTriangle {
+    pub a: Point3,
+    pub b: Point3,
+    pub c: Point3,
+    pub na: Vec3,
+    pub nb: Vec3,
+    pub nc: Vec3,
+    pub mat: Arc<dyn Material>,
+}
+
+impl Hittable for SmoothTriangle {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let (t, u, v) = hit_triangle(self.a, self.b, self.c, r, ray_t)?;
+
+        // Weigh every vertex normal by the barycentric coordinate of its vertex.
+        let normal = (1.0 - u - v) * self.na + u * self.nb + v * self.nc;
+        let outward_normal = normal
+            .try_normalize()
+            .unwrap_or_else(|| (self.b - self.a).cross(self.c - self.a).normalize());
+
+        let mut rec = HitRecord::new(r.at(t), t, self.mat.clone(), r, outward_normal);
+        (rec.u, rec.v) = (u, v);
+        rec.tangent = (self.b - self.a).normalize();
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        triangle_bounding_box(self.a, self.b, self.c)
+    }
+}
+
+/// Returns the ray parameter and the barycentric coordinates of `b` and `c` where `r` hits the
+/// triangle `a`, `b`, `c` within `ray_t`, using the Möller–Trumbore intersection.
+fn hit_triangle(
+    a: Point3,
+    b: Point3,
+    c: Point3,
+    r: &Ray,
+    ray_t: Interval,
+) -> Option<(f64, f64, f64)> {
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let pvec = r.direction().cross(edge2);
+    let det = edge1.dot(pvec);
+
+    // No hit if the ray is parallel to the triangle (or the triangle is degenerate).
+    if det.abs() < 1e-8 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+
+    // Barycentric coordinates of the hit point must lie within the triangle.
+    let tvec = r.origin - a;
+    let u = tvec.dot(pvec) * inv_det;
+    if !Interval::new(0.0, 1.0).contains(u) {
+        return None;
+    }
+
+    let qvec = tvec.cross(edge1);
+    let v = r.direction().dot(qvec) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(qvec) * inv_det;
+    if !ray_t.surrounds(t) {
+        return None;
+    }
+
+    Some((t, u, v))
+}
+
+fn triangle_bounding_box(a: Point3, b: Point3, c: Point3) -> Aabb {
+    Aabb::from_points(a.min(b).min(c), a.max(b).max(c)).pad()
+}
+
+/// A flat circle around `center`, facing along `normal`.
+pub struct Disk {
+    pub center: Point3,
+    pub normal: Vec3,
+    pub radius: f64,
+    pub mat: Arc<dyn Material>,
+}
+
+impl Hittable for Disk {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let normal = self.normal.normalize();
+        let denom = normal.dot(r.direction());
+
+        // No hit if the ray is parallel to the plane.
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = normal.dot(self.center - r.origin) / denom;
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+
+        // The hit point must lie within the radius on the plane.
+        let intersection = r.at(t);
+        let offset = intersection - self.center;
+        if offset.length_squared() > self.radius * self.radius {
+            return None;
+        }
+
+        // Polar coordinates: u goes around the rim and v goes out from the center.
+        let uvw = Onb::new(normal);
+        let phi = offset.dot(uvw.v).atan2(offset.dot(uvw.u)) + PI;
+
+        let mut rec = HitRecord::new(intersection, t, self.mat.clone(), r, normal);
+        (rec.u, rec.v) = (phi / (2.0 * PI), offset.length() / self.radius);
+        rec.tangent = (offset.dot(uvw.u) * uvw.v - offset.dot(uvw.v) * uvw.u).normalize_or_zero();
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let extent = disk_extent(self.normal, self.radius);
+
+        Aabb::from_points(self.center - extent, self.center + extent).pad()
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3, shadow_bias: f64) -> f64 {
+        let Some(rec) = self.hit(
+            &Ray::new(origin, direction),
+            Interval::new(shadow_bias, f64::INFINITY),
+        ) else {
+            return 0.0;
+        };
+
+        let area = PI * self.radius * self.radius;
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = (direction.dot(rec.normal) / direction.length()).abs();
+
+        distance_squared / (cosine * area)
+    }
+
+    fn random(&self, origin: Point3, rng: &mut dyn RngCore) -> Vec3 {
+        let p = self.radius * random_vec3_on_unit_disc(rng);
+        let point = self.center + Onb::new(self.normal).transform(p);
+
+        point - origin
+    }
+
+    fn sample_surface(&self, time: f64, rng: &mut dyn RngCore) -> Option<(HitRecord, f64)> {
+        let p = self.radius * random_vec3_on_unit_disc(rng);
+        let point = self.center + Onb::new(self.normal).transform(p);
+        let r = Ray::new(point + self.normal, -self.normal).with_time(time);
+        let rec = self.hit(&r, Interval::new(0.0, f64::INFINITY))?;
+
+        Some((rec, 1.0 / (PI * self.radius * self.radius)))
+    }
+}
+
+/// An endless flat plane through `point`, facing along `normal`. Unlike a huge sphere used as
+/// the ground, it stays flat all the way to the horizon.
+///
+/// Its texture coordinates are the distances along two fixed directions in the plane, so an
+/// `ImageTexture` that repeats is tiled once per unit of length, no matter where the camera is.
+///
+/// Its bounding box is all of space, so a `BvhNode` holding it tests every ray against it.
+pub struct InfinitePlane {
+    pub point: Point3,
+    pub normal: Vec3,
+    pub mat: Arc<dyn Material>,
+}
+
+impl Hittable for InfinitePlane {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let normal = self.normal.normalize();
+        let denom = normal.dot(r.direction());
+
+        // No hit if the ray is parallel to the plane.
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = normal.dot(self.point - r.origin) / denom;
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+
+        let intersection = r.at(t);
+        let offset = intersection - self.point;
+        let uvw = Onb::new(normal);
+
+        // The basis's v cross u is the normal, so taking them in that order keeps images from
+        // showing up mirrored when seen from the front.
+        let mut rec = HitRecord::new(intersection, t, self.mat.clone(), r, normal);
+        (rec.u, rec.v) = (offset.dot(uvw.v), offset.dot(uvw.u));
+        rec.tangent = uvw.v;
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::UNIVERSE
+    }
+}
+
+/// A sphere stretched by `radii` along the x, y and z axes, like a squashed planet.
+pub struct Ellipsoid {
+    pub center: Point3,
+    pub radii: Vec3,
+    pub mat: Arc<dyn Material>,
+}
+
+impl Hittable for Ellipsoid {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        // Scale the ray into the space where the ellipsoid is the unit sphere. The scaling is
+        // linear, so the ray keeps the same t values.
+        let origin = (r.origin - self.center) / self.radii;
+        let direction = r.direction() / self.radii;
+
+        let a = direction.length_squared();
+        let h = -direction.dot(origin);
+        let c = origin.length_squared() - 1.0;
+        let discriminant = h * h - a * c;
+
+        if discriminant < 0.0 {
+            return None;
+        }
+
+        let sqrtd = discriminant.sqrt();
+
+        // Find the nearest root that lies in the acceptable range.
+        let mut root = (h - sqrtd) / a;
+        if !ray_t.surrounds(root) {
+            root = (h + sqrtd) / a;
+            if !ray_t.surrounds(root) {
+                return None;
+            }
+        }
+
+        // Normals transform with the inverse transpose of the scaling, which divides by the
+        // radii once more.
+        let local = origin + root * direction;
+        let outward_normal = (local / self.radii).normalize();
+        let mut rec = HitRecord::new(r.at(root), root, self.mat.clone(), r, outward_normal);
+        (rec.u, rec.v) = Sphere::get_sphere_uv(local.normalize());
+        // Unlike normals, tangents scale along with the surface.
+        rec.tangent =
+            (Sphere::get_sphere_tangent(local.normalize()) * self.radii).normalize_or_zero();
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let radii = self.radii.abs();
+
+        Aabb::from_points(self.center - radii, self.center + radii)
+    }
+}
+
+/// A finite tube of `radius` around `axis`, from `base` up to `height` along the axis. The ends
+/// are closed with flat caps if `capped` is set.
+pub struct Cylinder {
+    pub base: Point3,
+    pub axis: Vec3,
+    pub radius: f64,
+    pub height: f64,
+    pub capped: bool,
+    pub mat: Arc<dyn Material>,
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let axis = self.axis.normalize();
+        let uvw = Onb::new(axis);
+        let oc = r.origin - self.base;
+
+        // The nearest hit found so far, as its t, point, outward normal and u, v.
+        let mut closest: Option<(f64, Point3, Vec3, (f64, f64))> = None;
+        let mut closest_so_far = ray_t.max;
+
+        // Intersect the infinite cylinder by leaving out the components along the axis, then
+        // keep only hits between the ends.
+        let d_perp = r.direction() - r.direction().dot(axis) * axis;
+        let oc_perp = oc - oc.dot(axis) * axis;
+        let a = d_perp.length_squared();
+        if a > 1e-12 {
+            let h = -d_perp.dot(oc_perp);
+            let c = oc_perp.length_squared() - self.radius * self.radius;
+            let discriminant = h * h - a * c;
+
+            if discriminant >= 0.0 {
+                let sqrtd = discriminant.sqrt();
+                for root in [(h - sqrtd) / a, (h + sqrtd) / a] {
+                    if !Interval::new(ray_t.min, closest_so_far).surrounds(root) {
+                        continue;
+                    }
+
+                    let p = r.at(root);
+                    let along = (p - self.base).dot(axis);
+                    if !Interval::new(0.0, self.height).contains(along) {
+                        continue;
+                    }
+
+                    let radial = p - self.base - along * axis;
+                    let phi = radial.dot(uvw.v).atan2(radial.dot(uvw.u)) + PI;
+                    let uv = (phi / (2.0 * PI), along / self.height);
+                    closest = Some((root, p, radial / self.radius, uv));
+                    closest_so_far = root;
+                    break;
+                }
+            }
+        }
+
+        if self.capped {
+            let denom = axis.dot(r.direction());
+            if denom.abs() >= 1e-8 {
+                for (center, normal) in [(self.base, -axis), (self.base + self.height * axis, axis)]
+                {
+                    let t = axis.dot(center - r.origin) / denom;
+                    if !Interval::new(ray_t.min, closest_so_far).surrounds(t) {
+                        continue;
+                    }
+
+                    let p = r.at(t);
+                    let offset = p - center;
+                    if offset.length_squared() > self.radius * self.radius {
+                        continue;
+                    }
+
+                    let phi = offset.dot(uvw.v).atan2(offset.dot(uvw.u)) + PI;
+                    let uv = (phi / (2.0 * PI), offset.length() / self.radius);
+                    closest = Some((t, p, normal, uv));
+                    closest_so_far = t;
+                }
+            }
+        }
+
+        let (t, p, outward_normal, uv) = closest?;
+        let mut rec = HitRecord::new(p, t, self.mat.clone(), r, outward_normal);
+        (rec.u, rec.v) = uv;
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // The cylinder fits in the box around its two end circles.
+        let top = self.base + self.height * self.axis.normalize();
+        let extent = disk_extent(self.axis, self.radius);
+
+        Aabb::from_points(self.base - extent, self.base + extent)
+            .union(&Aabb::from_points(top - extent, top + extent))
+            .pad()
+    }
+}
+
+/// The open side of a cone with its tip at `apex`, widening along `axis` by `angle` degrees
+/// from the axis, up to `height` from the apex.
+pub struct Cone {
+    pub apex: Point3,
+    pub axis: Vec3,
+    pub angle: f64,
+    pub height: f64,
+    pub mat: Arc<dyn Material>,
+}
+
+impl Cone {
+    /// Tangent of the half-angle, kept short of flattening the cone into a plane.
+    fn tan_angle(&self) -> f64 {
+        self.angle.clamp(0.0, 89.9).to_radians().tan()
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let axis = self.axis.normalize();
+        let co = r.origin - self.apex;
+        let k = 1.0 + self.tan_angle().powi(2);
+
+        // Points q from the apex are on the double cone where |q|^2 = k * (q . axis)^2.
+        let d_axis = r.direction().dot(axis);
+        let co_axis = co.dot(axis);
+        let a = r.direction().length_squared() - k * d_axis * d_axis;
+        let h = k * d_axis * co_axis - r.direction().dot(co);
+        let c = co.length_squared() - k * co_axis * co_axis;
+
+        let roots = if a.abs() < 1e-12 {
+            // The ray is parallel to a line on the cone, so it crosses it at most once.
+            if h.abs() < 1e-12 {
+                return None;
+            }
+            [c / (2.0 * h), f64::NAN]
+        } else {
+            let discriminant = h * h - a * c;
+            if discriminant < 0.0 {
+                return None;
+            }
+            let sqrtd = discriminant.sqrt();
+            let (t0, t1) = ((h - sqrtd) / a, (h + sqrtd) / a);
+            [t0.min(t1), t0.max(t1)]
+        };
+
+        // Keep the nearest hit on the nappe towards `axis`, within the height.
+        for root in roots {
+            if !ray_t.surrounds(root) {
+                continue;
+            }
+
+            let p = r.at(root);
+            let q = p - self.apex;
+            let along = q.dot(axis);
+            if !Interval::new(0.0, self.height).contains(along) {
+                continue;
+            }
+
+            // The gradient of the cone equation points out of the cone. It vanishes at the
+            // apex, where any direction backwards along the axis will do.
+            let outward_normal = (q - k * along * axis).try_normalize().unwrap_or(-axis);
+
+            let uvw = Onb::new(axis);
+            let phi = q.dot(uvw.v).atan2(q.dot(uvw.u)) + PI;
+
+            let mut rec = HitRecord::new(p, root, self.mat.clone(), r, outward_normal);
+            (rec.u, rec.v) = (phi / (2.0 * PI), along / self.height);
+            return Some(rec);
+        }
+
+        None
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // The cone fits in the box around its apex and its base circle.
+        let base = self.apex + self.height * self.axis.normalize();
+        let extent = disk_extent(self.axis, self.height * self.tan_angle());
+
+        Aabb::from_points(self.apex, self.apex)
+            .union(&Aabb::from_points(base - extent, base + extent))
+            .pad()
+    }
+}
+
+/// Returns how far a circle of `radius` facing along `normal` reaches from its center along each
+/// axis, which is the radius scaled by how much the circle leans towards that axis.
+fn disk_extent(normal: Vec3, radius: f64) -> Vec3 {
+    let normal = normal.normalize();
+    let extent = (Vec3::ONE - normal * normal).max(Vec3::ZERO);
+
+    radius * vec3(extent.x.sqrt(), extent.y.sqrt(), extent.z.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    fn gray() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Color::splat(0.5)))
+    }
+
+    /// A right triangle in the plane z = -1, facing the origin.
+    fn triangle() -> Triangle {
+        Triangle {
+            a: vec3(0.0, 0.0, -1.0),
+            b: vec3(1.0, 0.0, -1.0),
+            c: vec3(0.0, 1.0, -1.0),
+            mat: gray(),
+        }
+    }
+
+    fn ray_t() -> Interval {
+        Interval::new(0.001, f64::INFINITY)
+    }
+
+    #[test]
+    fn triangle_hit_at_centroid() {
+        let r = Ray::new(Point3::ZERO, vec3(1.0 / 3.0, 1.0 / 3.0, -1.0));
+        let rec = triangle()
+            .hit(&r, ray_t())
+            .expect("ray through the centroid hits");
+
+        assert!((rec.t - 1.0).abs() < 1e-12);
+        assert!((rec.u - 1.0 / 3.0).abs() < 1e-12 && (rec.v - 1.0 / 3.0).abs() < 1e-12);
+        assert!(rec.normal.abs_diff_eq(vec3(0.0, 0.0, 1.0), 1e-12));
+        assert!(rec.front_face);
+
+        // The hit is skipped outside the interval.
+        assert!(triangle().hit(&r, Interval::new(0.001, 0.5)).is_none());
+    }
+
+    #[test]
+    fn triangle_missed_just_outside_edges() {
+        for target in [
+            vec3(0.5005, 0.5005, -1.0),
+            vec3(0.5, -0.001, -1.0),
+            vec3(-0.001, 0.5, -1.0),
+        ] {
+            let r = Ray::new(Point3::ZERO, target);
+            assert!(
+                triangle().hit(&r, ray_t()).is_none(),
+                "ray to {target} hits"
+            );
+        }
+
+        // Just inside the long edge still hits.
+        let r = Ray::new(Point3::ZERO, vec3(0.4995, 0.4995, -1.0));
+        assert!(triangle().hit(&r, ray_t()).is_some());
+    }
+
+    #[test]
+    fn triangle_grazing_rays() {
+        // A ray in the plane of the triangle is parallel to it and never hits.
+        let r = Ray::new(vec3(-1.0, 0.2, -1.0), vec3(1.0, 0.0, 0.0));
+        assert!(triangle().hit(&r, ray_t()).is_none());
+
+        // A ray at a very shallow angle still hits where it crosses the plane.
+        let r = Ray::new(vec3(-1.0, 0.2, -0.999), vec3(1.0, 0.0, -0.001));
+        let rec = triangle().hit(&r, ray_t()).expect("shallow ray hits");
+        assert!(rec.p.abs_diff_eq(vec3(0.0, 0.2, -1.0), 1e-9));
+    }
+
+    #[test]
+    fn ray_through_box_center_hits_two_faces() {
+        // Corners in either order make the same box.
+        let sides = make_box(vec3(2.0, 2.0, 2.0), Point3::ZERO, gray());
+        let r = Ray::new(vec3(1.0, 1.0, -5.0), vec3(0.0, 0.0, 1.0));
+
+        let mut hits: Vec<HitRecord> = sides
+            .iter()
+            .filter_map(|side| side.hit(&r, ray_t()))
+            .collect();
+        hits.sort_by(|a, b| a.t.total_cmp(&b.t));
+
+        assert_eq!(hits.len(), 2);
+        assert!((hits[0].t - 5.0).abs() < 1e-12 && (hits[1].t - 7.0).abs() < 1e-12);
+        // The outward normals face the ray where it enters and away from it where it leaves.
+        assert!(hits[0].front_face && !hits[1].front_face);
+    }
+
+    #[test]
+    fn bounding_proxy_samples_what_it_shows() {
+        let sphere = || Box::new(Sphere::new(vec3(0.0, 0.0, -5.0), 1.0, gray()));
+        let mut proxy = BoundingProxy::new(sphere());
+        let (origin, direction) = (Point3::ZERO, vec3(0.0, 0.0, -1.0));
+
+        let boxed = make_box(vec3(-1.0, -1.0, -6.0), vec3(1.0, 1.0, -4.0), gray());
+        let box_pdf = boxed.pdf_value(origin, direction, 0.001);
+        assert!(box_pdf > 0.0);
+        assert_eq!(proxy.pdf_value(origin, direction, 0.001), box_pdf);
+
+        proxy.enabled = false;
+        let sphere_pdf = sphere().pdf_value(origin, direction, 0.001);
+        assert_eq!(proxy.pdf_value(origin, direction, 0.001), sphere_pdf);
+        let mut rng = StdRng::seed_from_u64(1);
+        let (rec, pdf) = proxy
+            .sample_surface(0.0, &mut rng)
+            .expect("spheres can be sampled");
+        assert!(((rec.p - vec3(0.0, 0.0, -5.0)).length() - 1.0).abs() < 1e-9);
+        assert!((pdf - 1.0 / (4.0 * PI)).abs() < 1e-12);
+    }
+}
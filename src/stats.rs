@@ -0,0 +1,61 @@
+use std::{fmt, fs::File, io, path::Path};
+
+use serde::Serialize;
+
+/// A machine-readable summary of a finished render.
+#[derive(Clone, Debug, Serialize)]
+pub struct RenderStats {
+    pub image_width: i32,
+    pub image_height: i32,
+    pub samples_per_pixel: i32,
+    /// Number of camera samples actually taken over the whole image
+    pub total_samples: u64,
+    pub wall_time_secs: f64,
+    /// Number of rays intersected with the world, including scattered rays
+    pub rays_traced: u64,
+    /// Number of rays cast from the camera, one per sample
+    pub primary_rays: u64,
+    /// Number of scattered rays and shadow rays
+    pub secondary_rays: u64,
+    pub rays_per_sec: f64,
+    /// Average number of times a path scattered before it ended
+    pub mean_bounces: f64,
+    /// Largest number of bounces any path reached
+    pub peak_bounce_depth: i32,
+    /// Number of paths that were cut off by `max_depth` before escaping or being absorbed
+    pub truncated_paths: u64,
+    /// Number of nodes in the BVHs of the world, 0 without any
+    pub bvh_nodes: u64,
+}
+
+impl RenderStats {
+    /// Writes the stats as pretty-printed JSON to `path`.
+    pub fn write_json(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for RenderStats {
+    /// A human-readable report, one stat per line.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Rendered {}x{} at {} samples per pixel in {:.2}s",
+            self.image_width, self.image_height, self.samples_per_pixel, self.wall_time_secs
+        )?;
+        writeln!(
+            f,
+            "Rays: {} ({} primary, {} secondary), {:.0} per second",
+            self.rays_traced, self.primary_rays, self.secondary_rays, self.rays_per_sec
+        )?;
+        writeln!(
+            f,
+            "Bounces: {:.2} per path on average, at most {}, {} paths cut off at the max depth",
+            self.mean_bounces, self.peak_bounce_depth, self.truncated_paths
+        )?;
+        writeln!(f, "BVH nodes: {}", self.bvh_nodes)
+    }
+}
@@ -0,0 +1,313 @@
+use std::{path::Path, sync::Arc};
+
+use image::ImageResult;
+use rand::RngCore;
+
+use crate::{color::Color, perlin::Perlin, HitRecord, Point3, Vec3};
+
+/// A spatially varying color. Like materials, textures are shared between threads, so they must
+/// be `Send + Sync`.
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+
+    /// The color where `rec` hit the surface. Textures that depend on more of the hit than its
+    /// texture coordinates and point, like the normal, override this; materials look textures
+    /// up through it.
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.value(rec.u, rec.v, &rec.p)
+    }
+}
+
+pub struct SolidColor(pub Color);
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.0
+    }
+}
+
+/// A 3D checkerboard of cubes with sides of length `scale`.
+pub struct CheckerTexture {
+    inv_scale: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+impl CheckerTexture {
+    pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        Self {
+            inv_scale: 1.0 / scale,
+            even,
+            odd,
+        }
+    }
+
+    pub fn from_colors(scale: f64, even: Color, odd: Color) -> Self {
+        Self::new(scale, Arc::new(SolidColor(even)), Arc::new(SolidColor(odd)))
+    }
+}
+
+impl CheckerTexture {
+    fn cell(&self, p: &Point3) -> &dyn Texture {
+        let x = (self.inv_scale * p.x).floor() as i64;
+        let y = (self.inv_scale * p.y).floor() as i64;
+        let z = (self.inv_scale * p.z).floor() as i64;
+
+        if (x + y + z).rem_euclid(2) == 0 {
+            self.even.as_ref()
+        } else {
+            self.odd.as_ref()
+        }
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.cell(p).value(u, v, p)
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.cell(&rec.p).value_at(rec)
+    }
+}
+
+/// A texture mapped from an image file using the surface's `(u, v)` coordinates. Lookups blend
+/// the four nearest texels, so the image stays smooth when magnified.
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    /// Linear colors, row-major from the top-left pixel
+    texels: Vec<Color>,
+    wrap_u: WrapMode,
+    wrap_v: WrapMode,
+}
+
+/// What an `ImageTexture` shows for coordinates outside of [0,1].
+#[derive(Clone, Copy, Default)]
+pub enum WrapMode {
+    /// The edge texels are stretched outwards.
+    #[default]
+    Clamp,
+    /// The image is tiled.
+    Repeat,
+}
+
+impl WrapMode {
+    /// Maps a texel index, possibly out of bounds, to one within `0..len`.
+    fn texel(self, index: i64, len: usize) -> usize {
+        match self {
+            WrapMode::Clamp => index.clamp(0, len as i64 - 1) as usize,
+            WrapMode::Repeat => index.rem_euclid(len as i64) as usize,
+        }
+    }
+}
+
+impl ImageTexture {
+    /// Loads a PNG or JPEG image. The image is assumed to be gamma encoded like our own output,
+    /// and is converted back to linear colors.
+    pub fn load(path: impl AsRef<Path>) -> ImageResult<Self> {
+        Self::load_with(path, |c| c.powi(2))
+    }
+
+    /// Loads a PNG or JPEG image of data rather than colors, like a normal map, keeping the
+    /// values as they're stored.
+    pub fn load_linear(path: impl AsRef<Path>) -> ImageResult<Self> {
+        Self::load_with(path, |c| c)
+    }
+
+    /// Loads an image, converting every channel value in [0,1] with `decode`.
+    fn load_with(path: impl AsRef<Path>, decode: fn(f64) -> f64) -> ImageResult<Self> {
+        let img = image::open(path)?.into_rgb8();
+
+        let texels = img
+            .pixels()
+            .map(|pixel| {
+                let [r, g, b] = pixel.0.map(|c| decode(c as f64 / 255.0));
+                Color::new(r, g, b)
+            })
+            .collect();
+
+        Ok(Self {
+            width: img.width() as usize,
+            height: img.height() as usize,
+            texels,
+            wrap_u: WrapMode::default(),
+            wrap_v: WrapMode::default(),
+        })
+    }
+
+    /// Sets how coordinates outside of [0,1] are handled along `u` and `v`.
+    pub fn with_wrap(self, wrap_u: WrapMode, wrap_v: WrapMode) -> Self {
+        Self {
+            wrap_u,
+            wrap_v,
+            ..self
+        }
+    }
+
+    fn texel(&self, i: i64, j: i64) -> Color {
+        let i = self.wrap_u.texel(i, self.width);
+        let j = self.wrap_v.texel(j, self.height);
+
+        self.texels[j * self.width + i]
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+        // If we have no texture data, then return solid cyan as a debugging aid.
+        if self.texels.is_empty() {
+            return Color::new(0.0, 1.0, 1.0);
+        }
+
+        // Find the position in texels, relative to texel centers. Image rows go from top to
+        // bottom, but v goes up.
+        let x = u * self.width as f64 - 0.5;
+        let y = (1.0 - v) * self.height as f64 - 0.5;
+        let (i, j) = (x.floor(), y.floor());
+        let (s, t) = (x - i, y - j);
+        let (i, j) = (i as i64, j as i64);
+
+        let top = self.texel(i, j).lerp(self.texel(i + 1, j), s);
+        let bottom = self.texel(i, j + 1).lerp(self.texel(i + 1, j + 1), s);
+        top.lerp(bottom, t)
+    }
+}
+
+/// Projects a texture onto the surface along each of the world axes, and blends the three by how
+/// much the surface faces along each axis. Geometry without texture coordinates, or with
+/// stretched ones, is textured evenly without seams.
+///
+/// The texture is looked up at the hit point's world YZ, XZ and XY coordinates divided by
+/// `scale` as its `(u, v)`, so image textures should repeat. Higher `sharpness` narrows the
+/// blend between the projections, e.g. 1.0 is soft and 8.0 nearly hard-edged.
+pub struct TriplanarTexture {
+    pub tex: Arc<dyn Texture>,
+    pub scale: f64,
+    pub sharpness: f64,
+}
+
+impl TriplanarTexture {
+    pub fn new(tex: Arc<dyn Texture>, scale: f64, sharpness: f64) -> Self {
+        Self {
+            tex,
+            scale,
+            sharpness,
+        }
+    }
+
+    /// Returns how much the projections along X, Y and Z show on a surface with `normal`. They
+    /// add up to 1, and are equal for a zero normal, like those of a `ConstantMedium`.
+    pub fn weights(&self, normal: Vec3) -> Vec3 {
+        let weights = normal.abs().powf(self.sharpness);
+        let sum = weights.x + weights.y + weights.z;
+        if sum >= 1e-12 {
+            weights / sum
+        } else {
+            Vec3::splat(1.0 / 3.0)
+        }
+    }
+
+    fn blend(&self, weights: Vec3, p: &Point3) -> Color {
+        let q = *p / self.scale;
+        weights.x * self.tex.value(q.y, q.z, p)
+            + weights.y * self.tex.value(q.x, q.z, p)
+            + weights.z * self.tex.value(q.x, q.y, p)
+    }
+}
+
+impl Texture for TriplanarTexture {
+    /// Without a normal to blend by, the three projections are averaged evenly.
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        self.blend(Vec3::splat(1.0 / 3.0), p)
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.blend(self.weights(rec.normal), &rec.p)
+    }
+}
+
+/// A marble-like pattern of stripes along Z, distorted by turbulence.
+pub struct NoiseTexture {
+    noise: Perlin,
+    scale: f64,
+}
+
+impl NoiseTexture {
+    pub fn new(scale: f64, rng: &mut dyn RngCore) -> Self {
+        Self {
+            noise: Perlin::new(rng),
+            scale,
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        Color::splat(0.5) * (1.0 + (self.scale * p.z + 10.0 * self.noise.turb(p, 7)).sin())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::Lambertian, Ray};
+
+    /// Shows its texture coordinates as red and green.
+    struct Coordinates;
+
+    impl Texture for Coordinates {
+        fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+            Color::new(u, v, 0.0)
+        }
+    }
+
+    fn hit(p: Point3, normal: Vec3) -> HitRecord {
+        let mat = Arc::new(Lambertian::new(Color::ONE));
+        HitRecord::new(p, 1.0, mat, &Ray::new(p + normal, -normal), normal)
+    }
+
+    #[test]
+    fn triplanar_weights_add_up_to_one() {
+        for sharpness in [0.5, 1.0, 4.0, 16.0] {
+            let tex = TriplanarTexture::new(Arc::new(Coordinates), 1.0, sharpness);
+            for normal in [
+                Vec3::X,
+                Vec3::new(0.0, -1.0, 1.0).normalize(),
+                Vec3::new(1.0, 2.0, -3.0).normalize(),
+            ] {
+                let weights = tex.weights(normal);
+                assert!((weights.x + weights.y + weights.z - 1.0).abs() < 1e-12);
+                assert!(weights.min_element() >= 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn triplanar_blends_evenly_without_a_normal() {
+        let tex = TriplanarTexture::new(Arc::new(Coordinates), 1.0, 4.0);
+        let p = Point3::new(0.2, 0.4, 0.6);
+
+        assert_eq!(tex.weights(Vec3::ZERO), Vec3::splat(1.0 / 3.0));
+        let color = tex.value_at(&hit(p, Vec3::ZERO));
+        assert!(color.is_finite());
+        assert!(color.abs_diff_eq(tex.value(0.0, 0.0, &p), 1e-12));
+    }
+
+    #[test]
+    fn triplanar_projects_along_the_normal() {
+        let tex = TriplanarTexture::new(Arc::new(Coordinates), 2.0, 4.0);
+        let p = Point3::new(0.2, 0.4, 0.6);
+
+        // Flat faces see a single projection, scaled down.
+        let up = tex.value_at(&hit(p, Vec3::Y));
+        assert!(up.abs_diff_eq(Color::new(0.1, 0.3, 0.0), 1e-12));
+        let side = tex.value_at(&hit(p, -Vec3::X));
+        assert!(side.abs_diff_eq(Color::new(0.2, 0.3, 0.0), 1e-12));
+
+        // Diagonal ones blend them evenly.
+        let diagonal = tex.value_at(&hit(p, Vec3::new(1.0, 0.0, 1.0).normalize()));
+        let expected = 0.5 * (Color::new(0.2, 0.3, 0.0) + Color::new(0.1, 0.2, 0.0));
+        assert!(diagonal.abs_diff_eq(expected, 1e-12));
+    }
+}
@@ -0,0 +1,251 @@
+use rand::RngCore;
+
+use crate::{aabb::Aabb, vec3, HitRecord, Hittable, Interval, Point3, Ray, Vec3};
+
+/// Moves an object by `offset` without touching its geometry.
+pub struct Translate {
+    pub object: Box<dyn Hittable>,
+    pub offset: Vec3,
+}
+
+impl Hittable for Translate {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        // Move the ray backwards by the offset, into object space.
+        let offset_r = Ray {
+            origin: r.origin - self.offset,
+            ..*r
+        };
+
+        // Determine whether an intersection exists along the offset ray, and if so, where.
+        let mut rec = self.object.hit(&offset_r, ray_t)?;
+
+        // Move the intersection point forwards by the offset, back into world space.
+        rec.p += self.offset;
+        Some(rec)
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3, shadow_bias: f64) -> f64 {
+        self.object
+            .pdf_value(origin - self.offset, direction, shadow_bias)
+    }
+
+    fn random(&self, origin: Point3, rng: &mut dyn RngCore) -> Vec3 {
+        self.object.random(origin - self.offset, rng)
+    }
+
+    fn sample_surface(&self, time: f64, rng: &mut dyn RngCore) -> Option<(HitRecord, f64)> {
+        let (mut rec, pdf) = self.object.sample_surface(time, rng)?;
+        rec.p += self.offset;
+        Some((rec, pdf))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let bbox = self.object.bounding_box();
+        let shift = |ax: Interval, d: f64| Interval::new(ax.min + d, ax.max + d);
+
+        Aabb::new(
+            shift(bbox.x, self.offset.x),
+            shift(bbox.y, self.offset.y),
+            shift(bbox.z, self.offset.z),
+        )
+    }
+
+    fn bvh_node_count(&self) -> u64 {
+        self.object.bvh_node_count()
+    }
+}
+
+/// Rotates an object around the y axis.
+pub struct RotateY {
+    object: Box<dyn Hittable>,
+    sin_theta: f64,
+    cos_theta: f64,
+    bbox: Aabb,
+}
+
+impl RotateY {
+    /// Rotates `object` counter-clockwise by `angle` degrees, looking down from +y.
+    pub fn new(object: Box<dyn Hittable>, angle: f64) -> Self {
+        let radians = angle.to_radians();
+        let (sin_theta, cos_theta) = radians.sin_cos();
+
+        // The rotated box is the box around the eight rotated corners of the original. Boxes
+        // that are empty or unbounded across the axis have no corners to rotate, e.g. infinite
+        // ones would give `inf * 0.0 = NaN` bounds for right angles.
+        let original = object.bounding_box();
+        let bounded = |ax: Interval| ax.min.is_finite() && ax.max.is_finite();
+        let bbox = if original.x.min > original.x.max || original.z.min > original.z.max {
+            Aabb::EMPTY
+        } else if !bounded(original.x) || !bounded(original.z) {
+            Aabb::new(Interval::UNIVERSE, original.y, Interval::UNIVERSE)
+        } else {
+            let mut bbox = Aabb::EMPTY;
+            for x in [original.x.min, original.x.max] {
+                for y in [original.y.min, original.y.max] {
+                    for z in [original.z.min, original.z.max] {
+                        let corner = vec3(
+                            cos_theta * x + sin_theta * z,
+                            y,
+                            -sin_theta * x + cos_theta * z,
+                        );
+                        bbox = bbox.union(&Aabb::from_points(corner, corner));
+                    }
+                }
+            }
+            bbox
+        };
+
+        Self {
+            object,
+            sin_theta,
+            cos_theta,
+            bbox,
+        }
+    }
+
+    fn to_object(&self, v: Vec3) -> Vec3 {
+        vec3(
+            self.cos_theta * v.x - self.sin_theta * v.z,
+            v.y,
+            self.sin_theta * v.x + self.cos_theta * v.z,
+        )
+    }
+
+    fn to_world(&self, v: Vec3) -> Vec3 {
+        vec3(
+            self.cos_theta * v.x + self.sin_theta * v.z,
+            v.y,
+            -self.sin_theta * v.x + self.cos_theta * v.z,
+        )
+    }
+}
+
+impl Hittable for RotateY {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        // Transform the ray from world space to object space.
+        // Rotating keeps the direction's length, so the rotated ray can share its cached square.
+        let rotated_r = Ray {
+            origin: self.to_object(r.origin),
+            direction: self.to_object(r.direction()),
+            ..*r
+        };
+        debug_assert!(
+            (rotated_r.direction().length_squared() - r.length_squared()).abs()
+                <= 1e-9 * r.length_squared()
+        );
+
+        // Determine whether an intersection exists in object space, and if so, where.
+        let mut rec = self.object.hit(&rotated_r, ray_t)?;
+
+        // Transform the intersection from object space back to world space.
+        rec.p = self.to_world(rec.p);
+        rec.normal = self.to_world(rec.normal);
+        rec.tangent = self.to_world(rec.tangent);
+        rec.motion = self.to_world(rec.motion);
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn bvh_node_count(&self) -> u64 {
+        self.object.bvh_node_count()
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3, shadow_bias: f64) -> f64 {
+        self.object.pdf_value(
+            self.to_object(origin),
+            self.to_object(direction),
+            shadow_bias,
+        )
+    }
+
+    fn random(&self, origin: Point3, rng: &mut dyn RngCore) -> Vec3 {
+        self.to_world(self.object.random(self.to_object(origin), rng))
+    }
+
+    fn sample_surface(&self, time: f64, rng: &mut dyn RngCore) -> Option<(HitRecord, f64)> {
+        let (mut rec, pdf) = self.object.sample_surface(time, rng)?;
+        rec.p = self.to_world(rec.p);
+        rec.normal = self.to_world(rec.normal);
+        rec.tangent = self.to_world(rec.tangent);
+        rec.motion = self.to_world(rec.motion);
+        Some((rec, pdf))
+    }
+}
+
+/// Turns an object inside out: the side of its surface that faced away from its outward normal
+/// counts as the front, e.g. to make a one-sided `DiffuseLight` quad shine the other way.
+///
+/// Both `front_face` and `normal` of its hits are inverted, so the normal points away from the
+/// ray. Materials that scatter light would scatter it through the surface, so this is meant for
+/// emissive surfaces like area lights.
+pub struct FlipFace(pub Box<dyn Hittable>);
+
+impl Hittable for FlipFace {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let mut rec = self.0.hit(r, ray_t)?;
+        rec.front_face = !rec.front_face;
+        rec.normal = -rec.normal;
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.0.bounding_box()
+    }
+
+    fn bvh_node_count(&self) -> u64 {
+        self.0.bvh_node_count()
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3, shadow_bias: f64) -> f64 {
+        self.0.pdf_value(origin, direction, shadow_bias)
+    }
+
+    fn random(&self, origin: Point3, rng: &mut dyn RngCore) -> Vec3 {
+        self.0.random(origin, rng)
+    }
+
+    /// The point is seen from its new front, the side the object's outward normal faced away
+    /// from, and the normal is turned to face that side too.
+    fn sample_surface(&self, time: f64, rng: &mut dyn RngCore) -> Option<(HitRecord, f64)> {
+        let (mut rec, pdf) = self.0.sample_surface(time, rng)?;
+        rec.normal = -rec.normal;
+        Some((rec, pdf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{color::Color, material::DiffuseLight, Sphere};
+
+    fn light() -> Box<dyn Hittable> {
+        let emit = Color::splat(4.0);
+        Box::new(Sphere::new(
+            vec3(0.0, 0.0, -2.0),
+            0.5,
+            Arc::new(DiffuseLight { emit }),
+        ))
+    }
+
+    #[test]
+    fn flip_face_inverts_the_side_hit() {
+        let r = Ray::new(Point3::ZERO, vec3(0.0, 0.0, -1.0));
+        let ray_t = Interval::new(0.001, f64::INFINITY);
+        let rec = light().hit(&r, ray_t).unwrap();
+        let flipped = FlipFace(light()).hit(&r, ray_t).unwrap();
+
+        assert!(rec.front_face && !flipped.front_face);
+        assert_eq!(flipped.normal, -rec.normal);
+        assert_eq!((flipped.p, flipped.t), (rec.p, rec.t));
+
+        // Only the front of a diffuse light emits.
+        assert_eq!(rec.mat.emitted(&r, &rec), Color::splat(4.0));
+        assert_eq!(flipped.mat.emitted(&r, &flipped), Color::ZERO);
+    }
+}
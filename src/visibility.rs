@@ -0,0 +1,70 @@
+use rand::RngCore;
+
+use crate::{aabb::Aabb, HitRecord, Hittable, Interval, Point3, Ray, RayKind, Vec3};
+
+/// Which kinds of rays can see an object.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Visibility {
+    /// Visible when looked at directly
+    pub camera: bool,
+    /// Casts shadows, i.e. blocks shadow rays
+    pub shadow: bool,
+    /// Visible in reflections and refractions, and lit by bounced light
+    pub secondary: bool,
+}
+
+impl Visibility {
+    pub fn is_visible_to(&self, kind: RayKind) -> bool {
+        match kind {
+            RayKind::Primary => self.camera,
+            RayKind::Shadow => self.shadow,
+            RayKind::Secondary => self.secondary,
+        }
+    }
+}
+
+impl Default for Visibility {
+    fn default() -> Self {
+        Self {
+            camera: true,
+            shadow: true,
+            secondary: true,
+        }
+    }
+}
+
+/// Wraps an object so it's only hit by the kinds of rays allowed by `visibility`.
+pub struct WithVisibility {
+    pub object: Box<dyn Hittable>,
+    pub visibility: Visibility,
+}
+
+impl Hittable for WithVisibility {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        if !self.visibility.is_visible_to(r.kind) {
+            return None;
+        }
+
+        self.object.hit(r, ray_t)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.object.bounding_box()
+    }
+
+    fn bvh_node_count(&self) -> u64 {
+        self.object.bvh_node_count()
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3, shadow_bias: f64) -> f64 {
+        self.object.pdf_value(origin, direction, shadow_bias)
+    }
+
+    fn random(&self, origin: Point3, rng: &mut dyn RngCore) -> Vec3 {
+        self.object.random(origin, rng)
+    }
+
+    fn sample_surface(&self, time: f64, rng: &mut dyn RngCore) -> Option<(HitRecord, f64)> {
+        self.object.sample_surface(time, rng)
+    }
+}